@@ -0,0 +1,16 @@
+# mampf shell integration for fish
+#
+# Copy this file to ~/.config/fish/functions/mampfcd.fish or source it from
+# your config.fish, then use 'mampfcd' instead of 'mampf'. On 'quit' the
+# shell changes into the last directory visited in mampf, 'quitnocd' leaves
+# the shell where it was.
+
+function mampfcd --wraps mampf --description 'mampf, cd into the last directory on quit'
+    set -l tmp (mktemp -t mampf-choosedir.XXXXXX); or return 1
+    command mampf --choosedir $tmp $argv
+    set -l dir (cat -- $tmp)
+    rm -f -- $tmp
+    if test -n "$dir"; and test -d "$dir"; and test "$dir" != "$PWD"
+        cd -- $dir
+    end
+end
@@ -0,0 +1,19 @@
+# mampf shell integration for bash and zsh
+#
+# Source this file from your ~/.bashrc or ~/.zshrc:
+#
+#     source /path/to/mampf/shell/mampf.sh
+#
+# and use 'mampfcd' instead of 'mampf'. On 'quit' the shell changes into the
+# last directory visited in mampf, 'quitnocd' leaves the shell where it was.
+
+mampfcd() {
+    local tmp dir
+    tmp="$(mktemp -t mampf-choosedir.XXXXXX)" || return 1
+    command mampf --choosedir "$tmp" "$@"
+    dir="$(cat -- "$tmp")"
+    rm -f -- "$tmp"
+    if [ -n "$dir" ] && [ -d "$dir" ] && [ "$dir" != "$PWD" ]; then
+        cd -- "$dir" || return 1
+    fi
+}
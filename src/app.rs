@@ -1,9 +1,22 @@
 use crate::{
+    archive,
+    cli::Args,
     config::Config,
+    du,
+    file_ops::{self, Attributes, LinkKind, OpReport, Transfer},
     fm_state::FMState,
-    util::{EntryStyle, PaneContent, PaneRole},
+    jobs::{Job, JobQueue},
+    journal::{Journal, Operation},
+    registers::{ClipMode, Registers},
+    shell::{Background, CommandOutput, ShowOutput},
+    trash,
+    util::{home_dir, read_preview, EntryStyle, PaneContent, PaneRole, Popup},
+    vfs,
+};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
 };
-use std::path::PathBuf;
 
 // State should hold all information to recreate a session
 // Tabbing will be a Vector of FMStates in the future
@@ -14,13 +27,429 @@ pub struct App {
     // TODO implement tabbing: switch to vector
     // TODO implement UI customization
     pub config: Config,
+    pub args: Args,
+    // whether the last directory should be written to the --choosedir file
+    pub cd_on_exit: bool,
+    // the files picked in --choosefiles mode
+    pub chosen: Vec<PathBuf>,
+    // a one line message for the status bar (results of operations)
+    pub message: Option<String>,
+    pub jobs: JobQueue,
+    // the file operations that can be undone
+    pub journal: Journal,
+    // a popup drawn on top of the panes (e.g. the list of jobs)
+    pub popup: Option<Popup>,
+    // the clipboard for yank, cut and paste
+    pub registers: Registers,
+    // the move jobs of pasted cut registers, by job id, the register is
+    // cleared once its job succeeded
+    pending_cuts: Vec<(usize, char, Vec<PathBuf>)>,
+    // the directory the current one was last compared with
+    pub other_dir: Option<PathBuf>,
+    // what the last shell command printed
+    pub last_output: Option<CommandOutput>,
+    // whether last_output waits to be shown in a popup
+    pub show_output: bool,
+    // the shell commands running in the background
+    pub background: Vec<Background>,
 }
 
 impl App {
-    pub fn from(config: Config) -> Self {
+    pub fn from(config: Config, args: Args) -> Self {
+        let start = args.start_path().unwrap_or_else(home_dir);
         Self {
-            fm_state: FMState::new(),
+            fm_state: FMState::new(start),
             config,
+            args,
+            cd_on_exit: true,
+            chosen: Vec::new(),
+            message: None,
+            jobs: JobQueue::default(),
+            journal: Journal::default(),
+            popup: None,
+            registers: Registers::default(),
+            pending_cuts: Vec::new(),
+            other_dir: None,
+            last_output: None,
+            show_output: false,
+            background: Vec::new(),
+        }
+    }
+
+    // copies the sources to their destinations in the background
+    pub fn copy_transfers(&mut self, transfers: Vec<Transfer>) {
+        let title = format!("copy {} entries", transfers.len());
+        let sources = transfers.iter().map(|t| t.source.clone()).collect();
+        self.jobs.spawn(title, "copied", sources, move |ctx| {
+            file_ops::copy_transfers(&transfers, ctx)
+        });
+    }
+
+    // moves the sources to their destinations in the background
+    pub fn move_transfers(&mut self, transfers: Vec<Transfer>) -> usize {
+        let title = format!("move {} entries", transfers.len());
+        let sources = transfers.iter().map(|t| t.source.clone()).collect();
+        self.jobs.spawn(title, "moved", sources, move |ctx| {
+            file_ops::move_transfers(&transfers, ctx)
+        })
+    }
+
+    // puts the marked entries (or the focused one) into the active register,
+    // cut entries get moved on paste instead of copied
+    pub fn yank_selection(&mut self, mode: ClipMode) {
+        let selection = self.fm_state.get_selection();
+        if selection.is_empty() {
+            return;
+        }
+        self.registers.set(mode, selection);
+        self.message = self.registers.describe();
+        self.fm_state.unmark_all();
+    }
+
+    // creates links to the sources at their destinations in the background
+    pub fn link_transfers(&mut self, transfers: Vec<Transfer>, kind: LinkKind) {
+        let title = format!("link {} entries", transfers.len());
+        let sources = transfers.iter().map(|t| t.source.clone()).collect();
+        self.jobs.spawn(title, "linked", sources, move |ctx| {
+            file_ops::link_transfers(&transfers, kind, ctx)
+        });
+    }
+
+    // copies or moves the content of the active register, a cut register
+    // is emptied since its entries are gone afterwards
+    pub fn paste(&mut self, mode: ClipMode, transfers: Vec<Transfer>) {
+        match mode {
+            ClipMode::Yank => self.copy_transfers(transfers),
+            ClipMode::Cut => {
+                let id = self.move_transfers(transfers);
+                if let Some(register) = self.registers.get() {
+                    let paths = register.paths.clone();
+                    self.pending_cuts.push((id, self.registers.active, paths));
+                }
+            }
+        }
+    }
+
+    // deletes the marked entries (or the focused one) permanently in the background
+    pub fn delete_selection(&mut self) {
+        let selection = self.fm_state.get_selection();
+        self.delete_paths(selection);
+    }
+
+    pub fn delete_paths(&mut self, paths: Vec<PathBuf>) {
+        let title = format!("delete {} entries", paths.len());
+        let sources = paths.clone();
+        self.jobs.spawn(title, "deleted", paths, move |ctx| {
+            file_ops::delete(&sources, ctx)
+        });
+    }
+
+    // moves the marked entries (or the focused one) into the trash in the background
+    pub fn trash_selection(&mut self) {
+        let selection = self.fm_state.get_selection();
+        self.trash_paths(selection);
+    }
+
+    pub fn trash_paths(&mut self, paths: Vec<PathBuf>) {
+        let title = format!("trash {} entries", paths.len());
+        let sources = paths.clone();
+        self.jobs.spawn(title, "trashed", paths, move |ctx| {
+            trash::trash(&sources, ctx)
+        });
+    }
+
+    // changes mode and owner of the given entries in the background
+    pub fn set_attributes(&mut self, paths: Vec<PathBuf>, attributes: Attributes, recursive: bool) {
+        let title = format!("chmod {} entries", paths.len());
+        let sources = paths.clone();
+        self.jobs.spawn(title, "changed", paths, move |ctx| {
+            file_ops::set_attributes(&sources, attributes, recursive, ctx)
+        });
+    }
+
+    // calculates the sizes of all directories in the current directory in
+    // the background, they show up in the listing once they are known
+    pub fn request_dir_sizes(&self) {
+        for pathb in self.fm_state.list_current() {
+            if pathb.is_dir() {
+                du::request(&pathb);
+            }
+        }
+    }
+
+    // extracts the marked (or the focused) archives in the background, each
+    // into a directory named like the archive in the current directory
+    pub fn extract_selection(&mut self) {
+        let archives = self
+            .fm_state
+            .get_selection()
+            .into_iter()
+            .filter(|pathb| !vfs::is_virtual(pathb) && archive::Kind::of(pathb).is_some())
+            .collect::<Vec<PathBuf>>();
+        if archives.is_empty() {
+            self.message = Some("no archive selected".to_string());
+            return;
+        }
+        // archives are browsed like directories but can't be written to
+        let dest_dir = self.fm_state.get_currentdir();
+        if !dest_dir.is_dir() {
+            self.message = Some("extract failed: archives are read-only".to_string());
+            return;
+        }
+        let title = format!("extract {} archives", archives.len());
+        let sources = archives.clone();
+        self.jobs.spawn(title, "extracted", archives, move |ctx| {
+            archive::extract_all(&sources, &dest_dir, ctx)
+        });
+    }
+
+    // packs the marked (or the focused) entries into the new archive name
+    // in the current directory, .tar.gz is used if the name has no known
+    // extension
+    pub fn compress(&mut self, name: &str) {
+        let selection = self.fm_state.get_selection();
+        if selection.is_empty() {
+            return;
+        }
+        let name = if archive::Kind::of(Path::new(name)).is_some() {
+            name.to_string()
+        } else {
+            format!("{}.tar.gz", name)
+        };
+        let dest_dir = self.fm_state.get_currentdir();
+        let dest = dest_dir.join(&name);
+        let checked = file_ops::valid_name(&name).and_then(|_| file_ops::check_writable(&dest));
+        if let Err(error) = checked {
+            self.message = Some(format!("compress failed: {}", error));
+            return;
+        }
+        if dest.symlink_metadata().is_ok() {
+            self.message = Some(format!("{} already exists", name));
+            return;
+        }
+        let title = format!("compress {}", name);
+        let sources = selection.clone();
+        self.jobs.spawn(title, "compressed", selection, move |ctx| {
+            archive::compress(&sources, &dest, ctx)
+        });
+    }
+
+    // handles the jobs that finished since the last call
+    pub fn update_jobs(&mut self) {
+        for (job, mut report) in self.jobs.take_finished() {
+            // cached directory sizes are outdated now
+            du::invalidate();
+            if let Some(idx) = self.pending_cuts.iter().position(|(id, ..)| *id == job.id) {
+                let (_, name, paths) = self.pending_cuts.remove(idx);
+                // after a failed move the cut paths can still be pasted again
+                if report.failed.is_empty() {
+                    self.registers.clear_cut(name, &paths);
+                }
+            }
+            self.finish_op(&job, &report);
+            let operations = std::mem::take(&mut report.operations);
+            self.journal.record(&job.title, operations);
+        }
+        let mut idx = 0;
+        while idx < self.background.len() {
+            match self.background[idx].take_output() {
+                Some(output) => {
+                    let background = self.background.remove(idx);
+                    self.finish_command(background.show, output);
+                }
+                None => idx += 1,
+            }
+        }
+    }
+
+    // shows the result of a shell command in the status bar and, if show
+    // says so, its output in a popup
+    pub fn finish_command(&mut self, show: ShowOutput, output: CommandOutput) {
+        // the command might have changed any file
+        du::invalidate();
+        self.fm_state.refocus();
+        if show != ShowOutput::Never {
+            self.message = Some(output.summary());
+        }
+        self.show_output = !output.lines.is_empty()
+            && match show {
+                ShowOutput::Never => false,
+                ShowOutput::OnError => output.error.is_some(),
+                ShowOutput::Always => true,
+            };
+        self.last_output = Some(output);
+    }
+
+    // whether the UI should be refreshed without waiting for a keypress
+    pub fn is_busy(&self) -> bool {
+        !self.jobs.jobs.is_empty() || !self.background.is_empty() || du::is_scanning()
+    }
+
+    pub fn rename_focused(&mut self, new_name: &str) {
+        if let Some(focused) = self.fm_state.get_focused() {
+            match file_ops::rename(&focused, new_name) {
+                Ok(dest) => {
+                    self.fm_state.unmark(&focused);
+                    self.fm_state.focus(&dest);
+                    let description = format!("rename to {}", new_name);
+                    let operation = Operation::Move {
+                        from: focused,
+                        to: dest,
+                    };
+                    self.journal.record(&description, vec![operation]);
+                    du::invalidate();
+                }
+                Err(error) => {
+                    self.message = Some(format!("rename failed: {}", error));
+                    let failed = [(focused.clone(), error)];
+                    self.keep_failures(&format!("rename {}", new_name), &failed);
+                }
+            }
+        }
+    }
+
+    // creates the directory path (nested ones like a/b/c included) in the
+    // current directory and focuses it
+    pub fn mkdir(&mut self, path: &str) {
+        match file_ops::mkdir(&self.fm_state.get_currentdir(), path) {
+            Ok(created) => {
+                if let Some(dest) = created.last() {
+                    self.fm_state.reveal(dest);
+                }
+                let description = format!("mkdir {}", path);
+                let operations = created
+                    .into_iter()
+                    .map(|path| Operation::Mkdir { path })
+                    .collect();
+                self.journal.record(&description, operations);
+                du::invalidate();
+            }
+            Err(error) => {
+                self.message = Some(format!("mkdir failed: {}", error));
+                let failed = [(self.fm_state.get_currentdir().join(path), error)];
+                self.keep_failures(&format!("mkdir {}", path), &failed);
+            }
+        }
+    }
+
+    // creates the file path (missing parents included) in the current
+    // directory and focuses it, a configured template is used as content
+    pub fn touch(&mut self, path: &str) {
+        let template = self
+            .config
+            .template_for(path)
+            .map(|template| template.to_path_buf());
+        match file_ops::touch(&self.fm_state.get_currentdir(), path, template.as_deref()) {
+            Ok((created, dest)) => {
+                self.fm_state.reveal(&dest);
+                let description = format!("touch {}", path);
+                let mut operations = created
+                    .into_iter()
+                    .map(|path| Operation::Mkdir { path })
+                    .collect::<Vec<Operation>>();
+                operations.push(Operation::Touch {
+                    path: dest,
+                    template,
+                });
+                self.journal.record(&description, operations);
+                du::invalidate();
+            }
+            Err(error) => {
+                self.message = Some(format!("touch failed: {}", error));
+                let failed = [(self.fm_state.get_currentdir().join(path), error)];
+                self.keep_failures(&format!("touch {}", path), &failed);
+            }
+        }
+    }
+
+    pub fn undo(&mut self) {
+        let result = self.journal.undo();
+        du::invalidate();
+        self.fm_state.refocus();
+        self.message = Some(result.unwrap_or_else(|conflict| conflict));
+    }
+
+    pub fn redo(&mut self) {
+        let result = self.journal.redo();
+        du::invalidate();
+        self.fm_state.refocus();
+        self.message = Some(result.unwrap_or_else(|conflict| conflict));
+    }
+
+    // unmarks every source that was handled without errors, fixes the
+    // focus and puts the summary into the status bar
+    fn finish_op(&mut self, job: &Job, report: &OpReport) {
+        for source in job.sources.iter() {
+            if !report
+                .failed
+                .iter()
+                .any(|(pathb, _)| pathb.starts_with(source))
+            {
+                self.fm_state.unmark(source);
+            }
+        }
+        self.fm_state.refocus();
+        self.message = Some(report.summary(&job.verb));
+        if !report.failed.is_empty() {
+            self.keep_failures(&job.title, &report.failed);
+        }
+    }
+
+    // keeps every failure of an operation for the output popup, the status
+    // bar only has room for the first one until the next keypress
+    fn keep_failures(&mut self, title: &str, failed: &[(PathBuf, std::io::Error)]) {
+        let lines = failed
+            .iter()
+            .map(|(pathb, error)| (format!("{}: {}", pathb.display(), error), true))
+            .collect();
+        self.last_output = Some(CommandOutput {
+            program: title.to_string(),
+            lines,
+            error: Some(format!("{} errors", failed.len())),
+        });
+        if let Some(message) = self.message.as_mut() {
+            message.push_str(" (:output lists all)");
+        }
+    }
+
+    // picks the marked files or, if nothing is marked, the focused file
+    // (only meaningful in --choosefiles mode). Returns whether anything was
+    // chosen, the session is ended by the caller
+    pub fn choose(&mut self) -> bool {
+        let chosen = self.fm_state.get_selection();
+        if chosen.is_empty() {
+            return false;
+        }
+        // the caller gets paths, members of archives have none
+        if chosen.iter().any(|pathb| vfs::is_virtual(pathb)) {
+            self.message = Some(String::from("files inside of archives can't be chosen"));
+            return false;
+        }
+        self.chosen = chosen
+            .into_iter()
+            .map(|pathb| std::path::absolute(&pathb).unwrap_or(pathb))
+            .collect();
+        true
+    }
+
+    // writes the chosen files newline separated into the --choosefiles file
+    // or, if it is '-', into the given output (the original stdout)
+    pub fn write_chosen(&self, stdout: Option<impl Write>) -> Result<(), std::io::Error> {
+        let path = match &self.args.choosefiles {
+            Some(path) if !self.chosen.is_empty() => path,
+            _ => return Ok(()),
+        };
+        let mut text = String::new();
+        for pathb in self.chosen.iter() {
+            text += &pathb.to_string_lossy();
+            text += "\n";
+        }
+        match stdout {
+            Some(mut stdout) if self.args.choose_to_stdout() => {
+                stdout.write_all(text.as_bytes())?;
+                stdout.flush()
+            }
+            _ => std::fs::write(path, text),
         }
     }
 
@@ -28,6 +457,18 @@ impl App {
         self.fm_state.is_exit()
     }
 
+    // writes the current directory to the --choosedir file so that a shell
+    // wrapper can cd into it after mampf exited
+    pub fn write_choosedir(&self) -> Result<(), std::io::Error> {
+        if let Some(choosedir) = &self.args.choosedir {
+            if self.cd_on_exit {
+                let current_dir = self.fm_state.get_currentdir();
+                std::fs::write(choosedir, current_dir.to_string_lossy().as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn get_current_fm_state(&self) -> &FMState {
         &self.fm_state
     }
@@ -46,7 +487,7 @@ impl App {
 
     pub fn get_style(&self, pathb: &PathBuf) -> (PathBuf, EntryStyle) {
         let mut style = EntryStyle::Blue;
-        if pathb.is_dir() {
+        if vfs::is_dir(pathb) {
             style = EntryStyle::Cyan;
         }
         if self.fm_state.get_marked().contains(pathb) {
@@ -80,25 +521,25 @@ impl App {
             self.fm_state
                 .list_prev(depth)
                 .iter()
-                .map(|x| self.get_style(&x))
+                .map(|x| self.get_style(x))
                 .collect::<Vec<(PathBuf, EntryStyle)>>(),
         )
     }
 
     pub fn get_content_right(&self) -> PaneContent {
         if let Some(focused_pathb) = &self.fm_state.get_focused() {
-            if focused_pathb.is_dir() {
+            if vfs::is_dir_cached(focused_pathb) {
                 PaneContent::DirElements(
                     self.fm_state
                         .list_next()
                         .iter()
-                        .map(|x| self.get_style(&x))
+                        .map(|x| self.get_style(x))
                         .collect::<Vec<(PathBuf, EntryStyle)>>(),
                 )
             } else {
-                match std::fs::read_to_string(focused_pathb) {
-                    Ok(text) => PaneContent::Text(text),
-                    Err(_) => PaneContent::None,
+                match read_preview(focused_pathb) {
+                    Some(text) => PaneContent::Text(text),
+                    None => PaneContent::None,
                 }
             }
         } else {
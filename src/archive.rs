@@ -0,0 +1,734 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufReader, Error, ErrorKind, Read, Write},
+    os::unix::fs::PermissionsExt,
+    path::{Component, Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+use crate::{
+    file_ops::{self, OpReport},
+    jobs::JobContext,
+    journal::Operation,
+    util,
+};
+
+// Reading of tar, tar.gz and zip archives, for browsing them like
+// directories (see vfs) and for extracting them, and writing of new ones.
+// Member paths are relative to the archive, members with paths leading out
+// of it (../x, /x) are ignored
+
+// the file type bits of a unix mode as stored in zip archives
+const S_IFMT: u32 = 0o170000;
+const S_IFLNK: u32 = 0o120000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+const EXTENSIONS: [(&str, Kind); 4] = [
+    (".tar.gz", Kind::TarGz),
+    (".tgz", Kind::TarGz),
+    (".tar", Kind::Tar),
+    (".zip", Kind::Zip),
+];
+
+impl Kind {
+    // the kind of an archive by its file name
+    pub fn of(pathb: &Path) -> Option<Self> {
+        let name = pathb.file_name()?.to_str()?.to_lowercase();
+        EXTENSIONS
+            .iter()
+            .find(|(extension, _)| name.ends_with(extension))
+            .map(|(_, kind)| *kind)
+    }
+}
+
+// the name of an archive without its extension (foo for foo.tar.gz)
+pub fn stem(archive: &Path) -> Option<String> {
+    let name = archive.file_name()?.to_str()?;
+    let lowercase = name.to_lowercase();
+    let (extension, _) = EXTENSIONS
+        .iter()
+        .find(|(extension, _)| lowercase.ends_with(extension))?;
+    let stem = &name[..name.len() - extension.len()];
+    if stem.is_empty() {
+        None
+    } else {
+        Some(stem.to_string())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub is_dir: bool,
+    pub len: u64,
+    pub modified: Option<u64>,
+}
+
+// all members of an archive, including the directories that only exist
+// as parents of other members
+#[derive(Debug, Default)]
+pub struct Index {
+    pub members: HashMap<PathBuf, Member>,
+    // the member paths in every directory, "" is the top of the archive
+    pub children: HashMap<PathBuf, Vec<PathBuf>>,
+}
+
+impl Index {
+    fn insert(&mut self, path: PathBuf, member: Member) {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() && !self.members.contains_key(parent) {
+                let implied = Member {
+                    is_dir: true,
+                    len: 0,
+                    modified: None,
+                };
+                self.insert(parent.to_path_buf(), implied);
+            }
+            if !self.members.contains_key(&path) {
+                let siblings = self.children.entry(parent.to_path_buf()).or_default();
+                siblings.push(path.clone());
+            }
+        }
+        // an explicit entry replaces an implied one
+        self.members.insert(path, member);
+    }
+
+    // the summed up size and the number of files of a member, "" is the
+    // whole archive
+    pub fn tree_size(&self, top: &Path) -> (u64, u64) {
+        self.members
+            .iter()
+            .filter(|(path, member)| !member.is_dir && path.starts_with(top))
+            .fold((0, 0), |(bytes, files), (_, member)| {
+                (bytes + member.len, files + 1)
+            })
+    }
+}
+
+// reads the list of members of an archive
+pub fn index(archive: &Path) -> Result<Index, Error> {
+    let mut index = Index::default();
+    match kind_of(archive)? {
+        Kind::Zip => {
+            let mut zip = open_zip(archive)?;
+            for idx in 0..zip.len() {
+                let file = zip.by_index(idx).map_err(zip_error)?;
+                if let Some(path) = file.enclosed_name().and_then(member_path) {
+                    let member = Member {
+                        is_dir: file.is_dir(),
+                        len: file.size(),
+                        modified: None,
+                    };
+                    index.insert(path, member);
+                }
+            }
+        }
+        kind => {
+            let mut tar = open_tar(archive, kind)?;
+            for entry in tar.entries()? {
+                let entry = entry?;
+                if let Some(path) = member_path(&entry.path()?) {
+                    let header = entry.header();
+                    let member = Member {
+                        is_dir: header.entry_type().is_dir(),
+                        len: header.size().unwrap_or(0),
+                        modified: header.mtime().ok(),
+                    };
+                    index.insert(path, member);
+                }
+            }
+        }
+    }
+    Ok(index)
+}
+
+// the start of a member file, at most limit bytes
+pub fn read(archive: &Path, member: &Path, limit: u64) -> Result<Vec<u8>, Error> {
+    let mut buffer = Vec::new();
+    match kind_of(archive)? {
+        Kind::Zip => {
+            let mut zip = open_zip(archive)?;
+            for idx in 0..zip.len() {
+                let file = zip.by_index(idx).map_err(zip_error)?;
+                if file.enclosed_name().and_then(member_path).as_deref() == Some(member) {
+                    file.take(limit).read_to_end(&mut buffer)?;
+                    return Ok(buffer);
+                }
+            }
+        }
+        kind => {
+            let mut tar = open_tar(archive, kind)?;
+            for entry in tar.entries()? {
+                let entry = entry?;
+                if member_path(&entry.path()?).as_deref() == Some(member) {
+                    entry.take(limit).read_to_end(&mut buffer)?;
+                    return Ok(buffer);
+                }
+            }
+        }
+    }
+    Err(Error::new(ErrorKind::NotFound, "no such member"))
+}
+
+// extracts a member (a file or a whole directory, "" for everything) to
+// dest, which must not exist yet
+pub fn extract(archive: &Path, member: &Path, dest: &Path, ctx: &JobContext) -> Result<(), Error> {
+    // nothing may be written outside of dest, not even through symlinks
+    // that were extracted before
+    let root = if member.as_os_str().is_empty() {
+        dest
+    } else {
+        dest.parent().unwrap_or(dest)
+    };
+    let mut found = false;
+    match kind_of(archive)? {
+        Kind::Zip => {
+            let mut zip = open_zip(archive)?;
+            for idx in 0..zip.len() {
+                ctx.checkpoint()?;
+                let mut file = zip.by_index(idx).map_err(zip_error)?;
+                let target = match file.enclosed_name().and_then(member_path) {
+                    Some(path) if path.starts_with(member) => target(dest, member, &path),
+                    _ => continue,
+                };
+                found = true;
+                prepare_target(&target, root)?;
+                if file.is_dir() {
+                    fs::create_dir_all(&target)?;
+                } else if file
+                    .unix_mode()
+                    .is_some_and(|mode| mode & S_IFMT == S_IFLNK)
+                {
+                    // the content of a symlink is its target
+                    let mut link = String::new();
+                    file.read_to_string(&mut link)?;
+                    std::os::unix::fs::symlink(link, &target)?;
+                } else {
+                    let mut out = fs::OpenOptions::new()
+                        .write(true)
+                        .create_new(true)
+                        .open(&target)?;
+                    let bytes = std::io::copy(&mut file, &mut out)?;
+                    ctx.advance(bytes);
+                    if let Some(mode) = file.unix_mode() {
+                        fs::set_permissions(&target, fs::Permissions::from_mode(mode & 0o7777))?;
+                    }
+                }
+                ctx.file_done();
+            }
+        }
+        kind => {
+            let mut tar = open_tar(archive, kind)?;
+            for entry in tar.entries()? {
+                ctx.checkpoint()?;
+                let mut entry = entry?;
+                let target = match member_path(&entry.path()?) {
+                    Some(path) if path.starts_with(member) => target(dest, member, &path),
+                    _ => continue,
+                };
+                found = true;
+                prepare_target(&target, root)?;
+                let bytes = entry.header().size().unwrap_or(0);
+                if entry.header().entry_type().is_hard_link() {
+                    // unpack would link to the name as written, relative
+                    // to the working directory or even absolute
+                    let link = entry.link_name()?.unwrap_or_default();
+                    fs::hard_link(link_source(&link, dest, member, root)?, &target)?;
+                } else {
+                    entry.unpack(&target)?;
+                }
+                ctx.advance(bytes);
+                ctx.file_done();
+            }
+        }
+    }
+    if found {
+        Ok(())
+    } else {
+        Err(Error::new(ErrorKind::NotFound, "no such member"))
+    }
+}
+
+// extracts every archive into a directory named like it inside of
+// dest_dir, so that archives without a top directory don't litter dest_dir.
+// An archive that has just that directory isn't nested twice
+pub fn extract_all(archives: &[PathBuf], dest_dir: &Path, ctx: &JobContext) -> OpReport {
+    let mut report = OpReport::default();
+    let mut plans = Vec::new();
+    for archive in archives {
+        match plan_extraction(archive, dest_dir) {
+            Ok((member, (bytes, files))) => {
+                ctx.add_total(bytes, files);
+                plans.push((archive, member));
+            }
+            Err(error) => report.failed.push((archive.clone(), error)),
+        }
+    }
+    for (archive, member) in plans {
+        if let Err(error) = ctx.checkpoint() {
+            report.failed.push((archive.clone(), error));
+            break;
+        }
+        let stem = stem(archive).unwrap_or_default();
+        let mut dest = dest_dir.join(&stem);
+        if dest.symlink_metadata().is_ok() {
+            dest = file_ops::free_name(&dest, |pathb| pathb.symlink_metadata().is_ok());
+        }
+        match extract(archive, &member, &dest, ctx) {
+            Ok(()) => {
+                report.operations.push(Operation::Extract {
+                    archive: archive.clone(),
+                    member,
+                    to: dest.clone(),
+                });
+                report.done.push(dest);
+            }
+            Err(error) => {
+                // nothing half extracted stays behind
+                if dest.symlink_metadata().is_ok() {
+                    let _ = file_ops::remove_path(&dest);
+                }
+                report.failed.push((archive.clone(), error));
+            }
+        }
+    }
+    report
+}
+
+// the member to extract ("" for everything) with its size and file count
+fn plan_extraction(archive: &Path, dest_dir: &Path) -> Result<(PathBuf, (u64, u64)), Error> {
+    let index = index(archive)?;
+    let stem =
+        stem(archive).ok_or_else(|| Error::new(ErrorKind::InvalidInput, "not an archive"))?;
+    let top = index
+        .children
+        .get(Path::new(""))
+        .cloned()
+        .unwrap_or_default();
+    let member = match top.as_slice() {
+        [only] if only == Path::new(&stem) && index.members[only].is_dir => only.clone(),
+        _ => PathBuf::new(),
+    };
+    if !dest_dir.is_dir() {
+        return Err(Error::new(ErrorKind::NotFound, "the destination is gone"));
+    }
+    let size = index.tree_size(&member);
+    Ok((member, size))
+}
+
+// writes all sources (directories recursively) into the new archive dest,
+// the kind of archive is taken from its name
+pub fn compress(sources: &[PathBuf], dest: &Path, ctx: &JobContext) -> OpReport {
+    let mut report = OpReport::default();
+    for source in sources {
+        let (bytes, files) = file_ops::tree_size(source);
+        ctx.add_total(bytes, files);
+    }
+    let result = create(dest, sources, ctx);
+    match result {
+        Ok(()) => {
+            report.operations.push(Operation::Compress {
+                sources: sources.to_vec(),
+                to: dest.to_path_buf(),
+            });
+            report.done.push(dest.to_path_buf());
+        }
+        Err(error) => report.failed.push((dest.to_path_buf(), error)),
+    }
+    report
+}
+
+// creates the archive, nothing is left behind if that fails
+pub fn create(dest: &Path, sources: &[PathBuf], ctx: &JobContext) -> Result<(), Error> {
+    let kind = kind_of(dest)?;
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(dest)?;
+    let result = match kind {
+        Kind::Zip => write_zip(file, sources, ctx),
+        Kind::TarGz => {
+            let encoder = GzEncoder::new(file, Compression::default());
+            write_tar(encoder, sources, ctx).and_then(|encoder| encoder.finish().map(|_| ()))
+        }
+        Kind::Tar => write_tar(file, sources, ctx).map(|_| ()),
+    };
+    if result.is_err() {
+        let _ = fs::remove_file(dest);
+    }
+    result
+}
+
+// every path below the sources with the name it gets in the archive,
+// parents before their content
+fn archive_entries(sources: &[PathBuf]) -> Result<Vec<(PathBuf, PathBuf)>, Error> {
+    fn walk(
+        pathb: &Path,
+        name: PathBuf,
+        entries: &mut Vec<(PathBuf, PathBuf)>,
+    ) -> Result<(), Error> {
+        let is_dir = pathb.symlink_metadata()?.is_dir();
+        entries.push((pathb.to_path_buf(), name.clone()));
+        if is_dir {
+            for entry in fs::read_dir(pathb)? {
+                let entry = entry?;
+                walk(&entry.path(), name.join(entry.file_name()), entries)?;
+            }
+        }
+        Ok(())
+    }
+    let mut entries = Vec::new();
+    for source in sources {
+        let name = source
+            .file_name()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "can't archive the root"))?;
+        walk(source, PathBuf::from(name), &mut entries)?;
+    }
+    Ok(entries)
+}
+
+fn write_tar<W: Write>(out: W, sources: &[PathBuf], ctx: &JobContext) -> Result<W, Error> {
+    let mut builder = tar::Builder::new(out);
+    // symlinks are stored as symlinks
+    builder.follow_symlinks(false);
+    for (pathb, name) in archive_entries(sources)? {
+        ctx.checkpoint()?;
+        builder.append_path_with_name(&pathb, &name)?;
+        let metadata = pathb.symlink_metadata()?;
+        if metadata.is_file() {
+            ctx.advance(metadata.len());
+            ctx.file_done();
+        }
+    }
+    builder.into_inner()
+}
+
+fn write_zip(out: File, sources: &[PathBuf], ctx: &JobContext) -> Result<(), Error> {
+    let mut zip = zip::ZipWriter::new(out);
+    for (pathb, name) in archive_entries(sources)? {
+        ctx.checkpoint()?;
+        let metadata = pathb.symlink_metadata()?;
+        let name = name.to_string_lossy().into_owned();
+        let mut options = zip::write::FileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated)
+            .unix_permissions(metadata.permissions().mode() & 0o7777)
+            .large_file(metadata.len() > u32::MAX as u64);
+        if let Some(time) = zip_time(&metadata) {
+            options = options.last_modified_time(time);
+        }
+        if metadata.file_type().is_symlink() {
+            let target = fs::read_link(&pathb)?;
+            zip.add_symlink(name, target.to_string_lossy(), options)
+                .map_err(zip_error)?;
+        } else if metadata.is_dir() {
+            zip.add_directory(name, options).map_err(zip_error)?;
+        } else {
+            zip.start_file(name, options).map_err(zip_error)?;
+            let bytes = std::io::copy(&mut File::open(&pathb)?, &mut zip)?;
+            ctx.advance(bytes);
+            ctx.file_done();
+        }
+    }
+    zip.finish().map_err(zip_error)?;
+    Ok(())
+}
+
+fn target(dest: &Path, member: &Path, path: &Path) -> PathBuf {
+    match path.strip_prefix(member) {
+        Ok(rel) if !rel.as_os_str().is_empty() => dest.join(rel),
+        _ => dest.to_path_buf(),
+    }
+}
+
+// the extracted file a hard link entry points to, it has to be one of the
+// files extracted before
+fn link_source(link: &Path, dest: &Path, member: &Path, root: &Path) -> Result<PathBuf, Error> {
+    let outside = || {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "hard link to {} leads out of the destination",
+                link.display()
+            ),
+        )
+    };
+    let source = match member_path(link) {
+        Some(path) if path.starts_with(member) => target(dest, member, &path),
+        Some(_) => {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                format!("hard link to {}, which isn't extracted", link.display()),
+            ))
+        }
+        None => return Err(outside()),
+    };
+    // not through symlinks that were extracted before either
+    if source.canonicalize()?.starts_with(root.canonicalize()?) {
+        Ok(source)
+    } else {
+        Err(outside())
+    }
+}
+
+// creates the parents of target and makes sure they are inside of root
+fn prepare_target(target: &Path, root: &Path) -> Result<(), Error> {
+    let parent = target.parent().unwrap_or(root);
+    fs::create_dir_all(parent)?;
+    if parent.canonicalize()?.starts_with(root.canonicalize()?) {
+        Ok(())
+    } else {
+        Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("{} leads out of the destination", target.display()),
+        ))
+    }
+}
+
+// the relative, normalized path of a member, None if it would lead out of
+// the archive
+fn member_path(pathb: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in pathb.components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    if normalized.as_os_str().is_empty() {
+        None
+    } else {
+        Some(normalized)
+    }
+}
+
+fn kind_of(archive: &Path) -> Result<Kind, Error> {
+    Kind::of(archive).ok_or_else(|| Error::new(ErrorKind::InvalidInput, "not an archive"))
+}
+
+fn open_tar(archive: &Path, kind: Kind) -> Result<tar::Archive<Box<dyn Read>>, Error> {
+    let file = BufReader::new(File::open(archive)?);
+    let reader: Box<dyn Read> = match kind {
+        Kind::TarGz => Box::new(GzDecoder::new(file)),
+        _ => Box::new(file),
+    };
+    Ok(tar::Archive::new(reader))
+}
+
+fn open_zip(archive: &Path) -> Result<zip::ZipArchive<BufReader<File>>, Error> {
+    let file = BufReader::new(File::open(archive)?);
+    zip::ZipArchive::new(file).map_err(zip_error)
+}
+
+// the modification time in the local time zip uses, None before 1980
+fn zip_time(metadata: &fs::Metadata) -> Option<zip::DateTime> {
+    let secs = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    let tm = util::local_time(secs.as_secs());
+    zip::DateTime::from_date_and_time(
+        (tm.tm_year + 1900) as u16,
+        (tm.tm_mon + 1) as u8,
+        tm.tm_mday as u8,
+        tm.tm_hour as u8,
+        tm.tm_min as u8,
+        tm.tm_sec as u8,
+    )
+    .ok()
+}
+
+fn zip_error(error: zip::result::ZipError) -> Error {
+    match error {
+        zip::result::ZipError::Io(error) => error,
+        error => Error::new(ErrorKind::InvalidData, error.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tar::{EntryType, Header};
+    use zip::{write::FileOptions, ZipWriter};
+
+    // a fresh directory, the tests run in parallel
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "mampf-test-archive-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // a name that must not show up anywhere but in the test directory
+    fn escaped_name(name: &str) -> String {
+        format!("mampf-test-escaped-{}-{}", name, std::process::id())
+    }
+
+    // (name, type, content or link target), the names are written as they
+    // are, the tar crate would refuse the dangerous ones
+    fn write_tar(archive: &Path, entries: &[(&str, EntryType, &str)]) {
+        let mut builder = tar::Builder::new(File::create(archive).unwrap());
+        for (name, entry_type, data) in entries {
+            let mut header = Header::new_gnu();
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_entry_type(*entry_type);
+            header.set_mode(0o644);
+            let content = if entry_type.is_file() {
+                data.as_bytes()
+            } else {
+                header.as_old_mut().linkname[..data.len()].copy_from_slice(data.as_bytes());
+                &[]
+            };
+            header.set_size(content.len() as u64);
+            header.set_cksum();
+            builder.append(&header, content).unwrap();
+        }
+        builder.finish().unwrap();
+    }
+
+    // (name, content), a symlink if the name is given as "name -> target"
+    fn write_zip(archive: &Path, entries: &[(&str, &str)]) {
+        let mut zip = ZipWriter::new(File::create(archive).unwrap());
+        for (name, data) in entries {
+            match name.split_once(" -> ") {
+                Some((name, target)) => zip
+                    .add_symlink(name, target, FileOptions::default())
+                    .unwrap(),
+                None => {
+                    zip.start_file(*name, FileOptions::default()).unwrap();
+                    zip.write_all(data.as_bytes()).unwrap();
+                }
+            }
+        }
+        zip.finish().unwrap();
+    }
+
+    fn extract_one(archive: &Path, dest_dir: &Path) -> OpReport {
+        extract_all(&[archive.to_path_buf()], dest_dir, &JobContext::default())
+    }
+
+    #[test]
+    fn tar_members_outside_are_skipped() {
+        let dir = test_dir("tar-outside");
+        let dest_dir = dir.join("out");
+        fs::create_dir(&dest_dir).unwrap();
+        let absolute = dir.join(escaped_name("absolute"));
+        let archive = dir.join("evil.tar");
+        write_tar(
+            &archive,
+            &[
+                ("ok", EntryType::Regular, "ok"),
+                ("../../escaped", EntryType::Regular, "x"),
+                (absolute.to_str().unwrap(), EntryType::Regular, "x"),
+            ],
+        );
+        let report = extract_one(&archive, &dest_dir);
+        assert!(report.failed.is_empty());
+        assert_eq!(fs::read_to_string(dest_dir.join("evil/ok")).unwrap(), "ok");
+        assert!(!dir.join("escaped").exists());
+        assert!(!absolute.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn tar_links_leading_outside_are_refused() {
+        let dir = test_dir("tar-links");
+        let escaped = escaped_name("tar");
+        for (name, entries) in [
+            // a file written through a symlink to /
+            (
+                "symlink.tar",
+                vec![
+                    ("root", EntryType::Symlink, "/"),
+                    (&*format!("root/{}", escaped), EntryType::Regular, "x"),
+                ],
+            ),
+            // a hard link to a file outside
+            (
+                "hardlink.tar",
+                vec![("passwd", EntryType::Link, "/etc/passwd")],
+            ),
+        ] {
+            let archive = dir.join(name);
+            write_tar(&archive, &entries);
+            let report = extract_one(&archive, &dir);
+            assert_eq!(report.failed.len(), 1, "{}", name);
+            // nothing half extracted stays behind
+            assert!(!dir.join(stem(&archive).unwrap()).exists(), "{}", name);
+        }
+        assert!(!Path::new("/").join(&escaped).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn zip_members_outside_are_refused() {
+        let dir = test_dir("zip");
+        let dest_dir = dir.join("out");
+        fs::create_dir(&dest_dir).unwrap();
+        let escaped = escaped_name("zip");
+        let absolute = dir.join(escaped_name("absolute"));
+        let archive = dir.join("skipped.zip");
+        write_zip(
+            &archive,
+            &[
+                ("ok", "ok"),
+                ("../../escaped", "x"),
+                (absolute.to_str().unwrap(), "x"),
+            ],
+        );
+        assert!(extract_one(&archive, &dest_dir).failed.is_empty());
+        assert_eq!(
+            fs::read_to_string(dest_dir.join("skipped/ok")).unwrap(),
+            "ok"
+        );
+        assert!(!dir.join("escaped").exists());
+        assert!(!absolute.exists());
+        let archive = dir.join("symlink.zip");
+        let through = format!("root/{}", escaped);
+        write_zip(&archive, &[("root -> /", ""), (&through, "x")]);
+        assert_eq!(extract_one(&archive, &dest_dir).failed.len(), 1);
+        assert!(!dest_dir.join("symlink").exists());
+        assert!(!Path::new("/").join(&escaped).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn extract_nests_only_once() {
+        let dir = test_dir("nesting");
+        // the archive has just the directory named like it
+        let archive = dir.join("project.tar");
+        write_tar(
+            &archive,
+            &[
+                ("project/", EntryType::Directory, ""),
+                ("project/a", EntryType::Regular, "a"),
+            ],
+        );
+        // a single directory with another name, and a loose file
+        let other = dir.join("other.zip");
+        write_zip(&other, &[("src/b", "b")]);
+        let loose = dir.join("loose.tar.gz");
+        let encoder = GzEncoder::new(File::create(&loose).unwrap(), Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        let mut header = Header::new_gnu();
+        header.set_size(1);
+        header.set_cksum();
+        builder.append_data(&mut header, "c", &b"c"[..]).unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+        let report = extract_all(&[archive, other, loose], &dir, &JobContext::default());
+        assert!(report.failed.is_empty());
+        assert_eq!(fs::read_to_string(dir.join("project/a")).unwrap(), "a");
+        assert_eq!(fs::read_to_string(dir.join("other/src/b")).unwrap(), "b");
+        assert_eq!(fs::read_to_string(dir.join("loose/c")).unwrap(), "c");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
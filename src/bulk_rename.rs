@@ -0,0 +1,195 @@
+use std::{
+    collections::HashSet,
+    fs,
+    io::{Error, ErrorKind, Write},
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::file_ops;
+
+// Bulk renaming in the style of vidir: the names are written to a file, one
+// per line, the user edits them in $EDITOR and the changed lines are turned
+// into a plan of renames. The plan is validated as a whole before anything
+// is touched, swapping names (a -> b, b -> a) is fine
+
+// writes the names into a new file in a directory only the user can enter,
+// like mkdtemp. Other users can't guess the path to plant a symlink there
+pub fn create_names_file(text: &str) -> Result<PathBuf, std::io::Error> {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.subsec_nanos());
+    for attempt in 0..100 {
+        let dir = std::env::temp_dir().join(format!(
+            "mampf-bulkrename-{}-{:x}",
+            std::process::id(),
+            nanos.wrapping_add(attempt)
+        ));
+        match fs::DirBuilder::new().mode(0o700).create(&dir) {
+            Ok(()) => {}
+            Err(error) if error.kind() == ErrorKind::AlreadyExists => continue,
+            Err(error) => return Err(error),
+        }
+        let names_file = dir.join("names.txt");
+        let result = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&names_file)
+            .and_then(|mut file| file.write_all(text.as_bytes()));
+        return match result {
+            Ok(()) => Ok(names_file),
+            Err(error) => {
+                remove_names_file(&names_file);
+                Err(error)
+            }
+        };
+    }
+    Err(Error::new(
+        ErrorKind::AlreadyExists,
+        "couldn't create a temporary directory",
+    ))
+}
+
+// removes the names file with its directory (and whatever the editor left
+// in there, like backup files)
+pub fn remove_names_file(names_file: &Path) {
+    if let Some(dir) = names_file.parent() {
+        let _ = fs::remove_dir_all(dir);
+    }
+}
+
+// the text written to the names file, one file name per line
+pub fn names_text(entries: &[PathBuf]) -> Result<String, String> {
+    let mut text = String::new();
+    for entry in entries {
+        let name = entry
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| format!("can't edit the name of {}", entry.display()))?;
+        if name.contains('\n') {
+            return Err(format!("{:?} contains a line break", name));
+        }
+        text += name;
+        text += "\n";
+    }
+    Ok(text)
+}
+
+// turns the edited names into (from, to) pairs of the entries that changed
+pub fn plan(entries: &[PathBuf], edited: &str) -> Result<Vec<(PathBuf, PathBuf)>, String> {
+    let lines = edited.lines().collect::<Vec<&str>>();
+    if lines.len() != entries.len() {
+        return Err(format!(
+            "expected {} lines but got {}, lines must not be added or removed",
+            entries.len(),
+            lines.len()
+        ));
+    }
+    let mut renames = Vec::new();
+    let mut targets = HashSet::new();
+    for (entry, line) in entries.iter().zip(lines) {
+        let name = file_ops::valid_name(line).map_err(|_| format!("invalid name {:?}", line))?;
+        let target = entry.with_file_name(name);
+        if !targets.insert(target.clone()) {
+            return Err(format!("{} would be used twice", target.display()));
+        }
+        if &target != entry {
+            renames.push((entry.clone(), target));
+        }
+    }
+    // a target may only exist if it gets renamed itself
+    let sources = renames
+        .iter()
+        .map(|(from, _)| from.as_path())
+        .collect::<HashSet<&Path>>();
+    for (_, to) in renames.iter() {
+        if to.symlink_metadata().is_ok() && !sources.contains(to.as_path()) {
+            return Err(format!("{} already exists", to.display()));
+        }
+    }
+    Ok(renames)
+}
+
+pub fn apply(renames: &[(PathBuf, PathBuf)]) -> Result<(), std::io::Error> {
+    file_ops::move_all(renames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // a fresh directory with the given files, the tests run in parallel
+    fn test_dir(name: &str, files: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mampf-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for file in files {
+            fs::write(dir.join(file), file).unwrap();
+        }
+        dir
+    }
+
+    fn entries(dir: &Path, names: &[&str]) -> Vec<PathBuf> {
+        names.iter().map(|name| dir.join(name)).collect()
+    }
+
+    #[test]
+    fn names_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let names_file = create_names_file("a\n").unwrap();
+        let dir = names_file.parent().unwrap().to_path_buf();
+        let mode = |pathb: &Path| fs::metadata(pathb).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&dir), 0o700);
+        assert_eq!(mode(&names_file), 0o600);
+        assert_eq!(fs::read_to_string(&names_file).unwrap(), "a\n");
+        // every bulk rename gets its own directory
+        let other = create_names_file("").unwrap();
+        assert_ne!(other.parent(), Some(dir.as_path()));
+        remove_names_file(&names_file);
+        remove_names_file(&other);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn plan_skips_unchanged_names() {
+        let dir = test_dir("unchanged", &["a", "b"]);
+        let renames = plan(&entries(&dir, &["a", "b"]), "a\nc\n").unwrap();
+        assert_eq!(renames, [(dir.join("b"), dir.join("c"))]);
+        assert!(plan(&entries(&dir, &["a", "b"]), "a\nb\n")
+            .unwrap()
+            .is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn plan_allows_cycles() {
+        let dir = test_dir("cycles", &["a", "b", "c"]);
+        let entries = entries(&dir, &["a", "b", "c"]);
+        let renames = plan(&entries, "b\nc\na\n").unwrap();
+        assert_eq!(renames.len(), 3);
+        apply(&renames).unwrap();
+        for (file, content) in [("a", "c"), ("b", "a"), ("c", "b")] {
+            assert_eq!(fs::read_to_string(dir.join(file)).unwrap(), content);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn plan_rejects_conflicts() {
+        let dir = test_dir("conflicts", &["a", "b", "taken"]);
+        let entries = entries(&dir, &["a", "b"]);
+        // the same name twice, an existing file that isn't renamed
+        assert!(plan(&entries, "c\nc\n").is_err());
+        assert!(plan(&entries, "a\na\n").is_err());
+        assert!(plan(&entries, "taken\nb\n").is_err());
+        // lines added or removed, names that aren't names
+        assert!(plan(&entries, "a\n").is_err());
+        assert!(plan(&entries, "a\nb\nc\n").is_err());
+        assert!(plan(&entries, "a\nx/y\n").is_err());
+        assert!(plan(&entries, "a\n..\n").is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
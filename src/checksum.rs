@@ -0,0 +1,211 @@
+use std::{
+    fs::File,
+    io::{Error, ErrorKind, Read},
+    path::{Path, PathBuf},
+};
+
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+use crate::jobs::JobContext;
+
+// Checksums of files and the verification of checksum files like the
+// SHA256SUMS that come with downloads. Checksum files have one
+// "<hex>  <path>" line per file (a '*' instead of the second space marks
+// binary mode), the algorithm is told apart by the length of the hex
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Algorithm {
+    Sha256,
+    Md5,
+    Crc32,
+}
+
+pub const ALGORITHMS: [Algorithm; 3] = [Algorithm::Sha256, Algorithm::Md5, Algorithm::Crc32];
+
+// the names checksum files usually have, looked for in the current directory
+pub const SUMS_FILES: [&str; 6] = [
+    "SHA256SUMS",
+    "sha256sums",
+    "SHA256SUMS.txt",
+    "sha256sums.txt",
+    "MD5SUMS",
+    "md5sums",
+];
+
+impl Algorithm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Md5 => "md5",
+            Algorithm::Crc32 => "crc32",
+        }
+    }
+
+    fn of_hex(hex: &str) -> Option<Self> {
+        match hex.len() {
+            64 => Some(Algorithm::Sha256),
+            32 => Some(Algorithm::Md5),
+            8 => Some(Algorithm::Crc32),
+            _ => None,
+        }
+    }
+
+    fn hasher(&self) -> Hasher {
+        match self {
+            Algorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            Algorithm::Md5 => Hasher::Md5(Md5::new()),
+            Algorithm::Crc32 => Hasher::Crc32(crc32fast::Hasher::new()),
+        }
+    }
+}
+
+enum Hasher {
+    Sha256(Sha256),
+    Md5(Md5),
+    Crc32(crc32fast::Hasher),
+}
+
+impl Hasher {
+    fn update(&mut self, bytes: &[u8]) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(bytes),
+            Hasher::Md5(hasher) => hasher.update(bytes),
+            Hasher::Crc32(hasher) => hasher.update(bytes),
+        }
+    }
+
+    fn finish(self) -> String {
+        match self {
+            Hasher::Sha256(hasher) => hex(&hasher.finalize()),
+            Hasher::Md5(hasher) => hex(&hasher.finalize()),
+            Hasher::Crc32(hasher) => format!("{:08x}", hasher.finalize()),
+        }
+    }
+}
+
+// the checksums of every file with all algorithms (in the order of
+// ALGORITHMS), the files are read once
+pub fn compute(paths: &[PathBuf], ctx: &JobContext) -> Vec<(PathBuf, Result<Vec<String>, Error>)> {
+    for pathb in paths {
+        let len = pathb.metadata().map_or(0, |metadata| metadata.len());
+        ctx.add_total(len, 1);
+    }
+    let mut results = Vec::new();
+    for pathb in paths {
+        if ctx.checkpoint().is_err() {
+            break;
+        }
+        results.push((pathb.clone(), checksums(pathb, &ALGORITHMS, ctx)));
+        ctx.file_done();
+    }
+    results
+}
+
+fn checksums(
+    pathb: &Path,
+    algorithms: &[Algorithm],
+    ctx: &JobContext,
+) -> Result<Vec<String>, Error> {
+    if pathb.is_dir() {
+        return Err(Error::new(ErrorKind::InvalidInput, "is a directory"));
+    }
+    let mut file = File::open(pathb)?;
+    let mut hashers = algorithms
+        .iter()
+        .map(Algorithm::hasher)
+        .collect::<Vec<Hasher>>();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        ctx.checkpoint()?;
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hashers
+            .iter_mut()
+            .for_each(|hasher| hasher.update(&buffer[..read]));
+        ctx.advance(read as u64);
+    }
+    Ok(hashers.into_iter().map(Hasher::finish).collect())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[derive(Debug, Default)]
+pub struct Verification {
+    pub ok: Vec<PathBuf>,
+    pub failed: Vec<PathBuf>,  // the content doesn't match
+    pub missing: Vec<PathBuf>, // missing or unreadable
+}
+
+// checks every file listed in the checksum file, paths are relative to the
+// directory of the checksum file
+pub fn verify(sums_file: &Path, ctx: &JobContext) -> Result<Verification, Error> {
+    let text = std::fs::read_to_string(sums_file)?;
+    let dir = sums_file.parent().unwrap_or_else(|| Path::new("/"));
+    let mut listed = Vec::new();
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        let (hex, name) = parse_line(line).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("unexpected line {:?}", line),
+            )
+        })?;
+        let algorithm = Algorithm::of_hex(hex)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "unknown checksum type"))?;
+        let pathb = dir.join(name);
+        let len = pathb.metadata().map_or(0, |metadata| metadata.len());
+        ctx.add_total(len, 1);
+        listed.push((pathb, algorithm, hex.to_lowercase()));
+    }
+    let mut verification = Verification::default();
+    for (pathb, algorithm, expected) in listed {
+        let actual = checksums(&pathb, &[algorithm], ctx).map(|mut sums| sums.remove(0));
+        match actual {
+            Ok(actual) if actual == expected => verification.ok.push(pathb),
+            Ok(_) => verification.failed.push(pathb),
+            Err(error) if error.kind() == ErrorKind::Interrupted => return Err(error),
+            Err(_) => verification.missing.push(pathb),
+        }
+        ctx.file_done();
+    }
+    Ok(verification)
+}
+
+// "<hex>  <name>" or "<hex> *<name>"
+fn parse_line(line: &str) -> Option<(&str, &str)> {
+    let (hex, rest) = line.split_once(' ')?;
+    let name = rest.strip_prefix(' ').or_else(|| rest.strip_prefix('*'))?;
+    if !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()) && !name.is_empty() {
+        Some((hex, name))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_line_modes() {
+        assert_eq!(parse_line("0a1b  file.txt"), Some(("0a1b", "file.txt")));
+        assert_eq!(parse_line("0A1B *file.bin"), Some(("0A1B", "file.bin")));
+        // only the first character after the space is the mode
+        assert_eq!(parse_line("ff   two spaces"), Some(("ff", " two spaces")));
+        assert_eq!(parse_line("ff **star"), Some(("ff", "*star")));
+    }
+
+    #[test]
+    fn parse_line_rejects_garbage() {
+        assert_eq!(parse_line(""), None);
+        assert_eq!(parse_line("0a1b"), None);
+        assert_eq!(parse_line("0a1b file"), None);
+        assert_eq!(parse_line("0a1b  "), None);
+        assert_eq!(parse_line("xyz  file"), None);
+        assert_eq!(parse_line(" *file"), None);
+    }
+}
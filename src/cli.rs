@@ -0,0 +1,169 @@
+use std::path::PathBuf;
+
+// Parsing of the command line arguments. mampf only knows a handful of
+// flags, so they are parsed by hand instead of pulling in a dependency
+
+pub const USAGE: &str = "\
+Usage: mampf [OPTIONS] [PATH]
+
+Starts in PATH (or $HOME). If PATH is a file, its directory is opened
+and the file is focused.
+
+Options:
+    --config FILE       use FILE instead of the default config file
+    --select FILE       open the directory of FILE and focus FILE
+    --choosedir FILE    write the last visited directory to FILE on quit
+                        (see shell/ for wrappers that cd into it)
+    --choosefiles FILE  pick files instead of opening them: 'in' on a file
+                        or 'choose' writes the chosen paths to FILE and
+                        quits, '-' writes them to stdout
+    --version           print the version and exit
+    --help              print this help and exit";
+
+#[derive(Debug, Clone, Default)]
+pub struct Args {
+    pub start: Option<PathBuf>,
+    pub config: Option<PathBuf>,
+    pub select: Option<PathBuf>,
+    pub choosedir: Option<PathBuf>,
+    pub choosefiles: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+pub enum Cli {
+    Run(Args),
+    Help,
+    Version,
+}
+
+impl Args {
+    pub fn is_picker(&self) -> bool {
+        self.choosefiles.is_some()
+    }
+
+    // whether the chosen files should be written to stdout
+    pub fn choose_to_stdout(&self) -> bool {
+        self.choosefiles.as_deref() == Some(std::path::Path::new("-"))
+    }
+
+    // the path the file manager should open with, --select wins over PATH
+    pub fn start_path(&self) -> Option<PathBuf> {
+        self.select.clone().or_else(|| self.start.clone())
+    }
+}
+
+pub fn parse(argv: impl Iterator<Item = String>) -> Result<Cli, String> {
+    // support the '--flag=value' spelling by splitting it up front
+    let mut argv = argv
+        .flat_map(|arg| match arg.find('=') {
+            Some(idx) if arg.starts_with("--") => {
+                vec![arg[..idx].to_string(), arg[idx + 1..].to_string()]
+            }
+            _ => vec![arg],
+        })
+        .collect::<Vec<String>>()
+        .into_iter();
+    let mut args = Args::default();
+    while let Some(arg) = argv.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Cli::Help),
+            "-V" | "--version" => return Ok(Cli::Version),
+            "--config" => {
+                let value = expect_value(&arg, argv.next())?;
+                args.config = Some(existing_path(&value, false)?);
+            }
+            "--select" => {
+                let value = expect_value(&arg, argv.next())?;
+                args.select = Some(existing_path(&value, true)?);
+            }
+            "--choosedir" => {
+                // the file doesn't have to exist yet, it is written on quit
+                args.choosedir = Some(PathBuf::from(expect_value(&arg, argv.next())?));
+            }
+            "--choosefiles" => {
+                args.choosefiles = Some(PathBuf::from(expect_value(&arg, argv.next())?));
+            }
+            flag if flag.starts_with('-') && flag != "-" => {
+                return Err(format!("unknown option '{}'", flag));
+            }
+            _ => {
+                if args.start.is_some() {
+                    return Err(format!("unexpected argument '{}'", arg));
+                }
+                args.start = Some(existing_path(&arg, true)?);
+            }
+        }
+    }
+    Ok(Cli::Run(args))
+}
+
+fn expect_value(flag: &str, value: Option<String>) -> Result<String, String> {
+    match value {
+        Some(value) if !value.is_empty() => Ok(value),
+        _ => Err(format!("option '{}' requires a value", flag)),
+    }
+}
+
+// resolves a path given on the command line, relative paths are relative to
+// the working directory the file manager was started in
+fn existing_path(path: &str, dir_allowed: bool) -> Result<PathBuf, String> {
+    let pathb = PathBuf::from(path);
+    if !pathb.exists() {
+        return Err(format!("'{}' does not exist", path));
+    }
+    if !dir_allowed && pathb.is_dir() {
+        return Err(format!("'{}' is a directory", path));
+    }
+    pathb
+        .canonicalize()
+        .map_err(|e| format!("couldn't resolve '{}': {}", path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn parse_args(argv: &[&str]) -> Result<Cli, String> {
+        parse(argv.iter().map(|arg| arg.to_string()))
+    }
+
+    fn run_args(argv: &[&str]) -> Args {
+        match parse_args(argv) {
+            Ok(Cli::Run(args)) => args,
+            other => panic!("{:?}", other),
+        }
+    }
+
+    const MANIFEST: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+
+    #[test]
+    fn parse_flags() {
+        let args = run_args(&["--config", MANIFEST, "--choosedir=/tmp/x", "/"]);
+        assert_eq!(args.config.as_deref(), Some(Path::new(MANIFEST)));
+        assert_eq!(args.choosedir.as_deref(), Some(Path::new("/tmp/x")));
+        assert_eq!(args.start.as_deref(), Some(Path::new("/")));
+        let args = run_args(&["--select", MANIFEST, "--choosefiles", "-"]);
+        assert_eq!(args.start_path().as_deref(), Some(Path::new(MANIFEST)));
+        assert!(args.is_picker() && args.choose_to_stdout());
+        assert!(matches!(parse_args(&["/", "--help"]), Ok(Cli::Help)));
+        assert!(matches!(parse_args(&["-V"]), Ok(Cli::Version)));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |argv: &[&str]| parse_args(argv).unwrap_err();
+        assert_eq!(error(&["--bogus"]), "unknown option '--bogus'");
+        assert_eq!(error(&["--config"]), "option '--config' requires a value");
+        assert_eq!(
+            error(&["--choosedir="]),
+            "option '--choosedir' requires a value"
+        );
+        assert_eq!(error(&["--config", "/"]), "'/' is a directory");
+        assert_eq!(error(&["/", "/"]), "unexpected argument '/'");
+        assert_eq!(
+            error(&["/does/not/exist"]),
+            "'/does/not/exist' does not exist"
+        );
+    }
+}
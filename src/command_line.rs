@@ -0,0 +1,162 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::{self, APP_COMMANDS},
+    keys::Action,
+    shell::{ShellCommand, ShowOutput},
+    util::home_dir,
+};
+
+// The command line (':'), on which everything that can be bound can be
+// typed as well: the name of an action ("sortbynew"), "jump <dir>",
+// "shell <command>" (or "!<command>") for sh -c and, if the first word is
+// no action, a shell command with placeholders like in the cmd bindings.
+// What was typed is kept in a history file that survives restarts
+
+// the entries kept in the history file
+const HISTORY_SIZE: usize = 500;
+
+// the commands that take an argument, completed like actions
+const COMMANDS: [&str; 2] = ["jump", "shell"];
+
+// what the input asks for, relative paths start in the current directory
+pub fn parse(input: &str, currentdir: &Path) -> Result<Action, String> {
+    let input = input.trim();
+    let (first, rest) = match input.split_once(char::is_whitespace) {
+        Some((first, rest)) => (first, rest.trim()),
+        None => (input, ""),
+    };
+    if let Some(script) = input.strip_prefix('!') {
+        return Ok(Action::ShellCmd(typed_command(script.trim(), true)));
+    }
+    match first {
+        "shell" if rest.is_empty() => Err(String::from("shell needs a command")),
+        "shell" => Ok(Action::ShellCmd(typed_command(rest, true))),
+        "jump" => {
+            let dir = if rest.is_empty() {
+                String::from("~")
+            } else if rest.starts_with('~') || rest.starts_with('$') {
+                rest.to_string()
+            } else {
+                currentdir.join(rest).to_string_lossy().into_owned()
+            };
+            match config::parse_jump_command(&dir) {
+                Some(pathb) => Ok(Action::Jump(pathb)),
+                None => Err(format!("{} doesn't exist", rest)),
+            }
+        }
+        _ => match config::parse_app_command(first) {
+            Some(_) if !rest.is_empty() => Err(format!("{} takes no arguments", first)),
+            Some(action) => Ok(action),
+            None => Ok(Action::ShellCmd(typed_command(input, false))),
+        },
+    }
+}
+
+// typed commands show their output, that's what they are usually run for
+fn typed_command(template: &str, shell: bool) -> ShellCommand {
+    let mut cmd = ShellCommand::new(template);
+    cmd.shell = shell;
+    cmd.output = ShowOutput::Always;
+    cmd
+}
+
+// every way the input could go on: action names for the first word, paths
+// for the others and previous commands starting like the input
+pub fn complete(input: &str, currentdir: &Path, history: &[String]) -> Vec<String> {
+    let mut candidates = Vec::new();
+    match input.rsplit_once(' ') {
+        None => {
+            let names = APP_COMMANDS.iter().map(|(name, _)| *name).chain(COMMANDS);
+            candidates.extend(
+                names
+                    .filter(|name| name.starts_with(input))
+                    .map(String::from),
+            );
+        }
+        Some((start, word)) => {
+            candidates.extend(
+                complete_path(word, currentdir)
+                    .into_iter()
+                    .map(|word| format!("{} {}", start, word)),
+            );
+        }
+    }
+    for previous in history.iter().rev() {
+        if previous.starts_with(input) && !candidates.contains(previous) {
+            candidates.push(previous.clone());
+        }
+    }
+    candidates
+}
+
+// the entries of the directory the word points into that start like its
+// last component, directories end with a /
+fn complete_path(word: &str, currentdir: &Path) -> Vec<String> {
+    let (dir_part, prefix) = match word.rfind('/') {
+        Some(idx) => (&word[..=idx], &word[idx + 1..]),
+        None => ("", word),
+    };
+    let dir = if let Some(rest) = dir_part.strip_prefix('~') {
+        home_dir().join(rest.trim_start_matches('/'))
+    } else {
+        currentdir.join(dir_part)
+    };
+    let mut names = fs::read_dir(&dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_str()?.to_string();
+            // dotfiles only if asked for
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let is_dir = entry.path().is_dir();
+            Some(format!(
+                "{}{}{}",
+                dir_part,
+                name,
+                if is_dir { "/" } else { "" }
+            ))
+        })
+        .collect::<Vec<String>>();
+    names.sort();
+    names
+}
+
+fn history_file() -> PathBuf {
+    let data_home = match std::env::var("XDG_DATA_HOME") {
+        Ok(data_home) if !data_home.is_empty() => PathBuf::from(data_home),
+        _ => home_dir().join(".local").join("share"),
+    };
+    data_home.join("mampf").join("history")
+}
+
+// the typed commands, the oldest first
+pub fn load_history() -> Vec<String> {
+    fs::read_to_string(history_file())
+        .map(|text| text.lines().map(String::from).collect())
+        .unwrap_or_default()
+}
+
+// appends the input to the history (an older copy of it is dropped)
+pub fn save_history(history: &mut Vec<String>, input: &str) -> Result<(), std::io::Error> {
+    history.retain(|previous| previous != input);
+    history.push(input.to_string());
+    let skip = history.len().saturating_sub(HISTORY_SIZE);
+    history.drain(..skip);
+    let path = history_file();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = fs::File::create(path)?;
+    for previous in history.iter() {
+        writeln!(file, "{}", previous)?;
+    }
+    Ok(())
+}
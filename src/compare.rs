@@ -0,0 +1,157 @@
+use std::{
+    fs::{self, File, Metadata},
+    io::{Error, Read},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+use crate::jobs::JobContext;
+
+// Compares two directory trees, e.g. a folder with its backup. Entries that
+// exist on one side only are reported without their content. Files of the
+// same size and modification time are taken as equal, like rsync does,
+// the content is only compared if the times differ
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Difference {
+    LeftOnly,
+    RightOnly,
+    Kind, // a file on one side, a directory on the other
+    Size,
+    Content,
+    Modified, // same content, different modification time
+}
+
+impl Difference {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Difference::LeftOnly => "left only",
+            Difference::RightOnly => "right only",
+            Difference::Kind => "type differs",
+            Difference::Size => "size differs",
+            Difference::Content => "content differs",
+            Difference::Modified => "time differs",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub path: PathBuf, // relative to both sides
+    pub difference: Difference,
+}
+
+// the differences between the trees below left and right, sorted by path
+pub fn compare(left: &Path, right: &Path, ctx: &JobContext) -> Result<Vec<Entry>, Error> {
+    let mut entries = Vec::new();
+    compare_dirs(left, right, Path::new(""), &mut entries, ctx)?;
+    entries.sort_by(|x, y| x.path.cmp(&y.path));
+    Ok(entries)
+}
+
+fn compare_dirs(
+    left: &Path,
+    right: &Path,
+    rel: &Path,
+    entries: &mut Vec<Entry>,
+    ctx: &JobContext,
+) -> Result<(), Error> {
+    ctx.checkpoint()?;
+    let mut names = list_names(&left.join(rel))?;
+    let right_names = list_names(&right.join(rel))?;
+    for name in right_names.iter() {
+        if !names.contains(name) {
+            names.push(name.clone());
+        }
+    }
+    for name in names {
+        let path = rel.join(&name);
+        let left_meta = left.join(&path).symlink_metadata().ok();
+        let right_meta = right.join(&path).symlink_metadata().ok();
+        let difference = match (left_meta, right_meta) {
+            (Some(_), None) => Some(Difference::LeftOnly),
+            (None, Some(_)) => Some(Difference::RightOnly),
+            (Some(l), Some(r)) if l.is_dir() && r.is_dir() => {
+                compare_dirs(left, right, &path, entries, ctx)?;
+                None
+            }
+            (Some(l), Some(r)) if l.is_dir() || r.is_dir() => Some(Difference::Kind),
+            (Some(l), Some(r)) => {
+                compare_files(&left.join(&path), &l, &right.join(&path), &r, ctx)?
+            }
+            (None, None) => None,
+        };
+        if let Some(difference) = difference {
+            entries.push(Entry { path, difference });
+        }
+    }
+    Ok(())
+}
+
+fn list_names(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| PathBuf::from(entry.file_name())))
+        .collect()
+}
+
+fn compare_files(
+    left: &Path,
+    left_meta: &Metadata,
+    right: &Path,
+    right_meta: &Metadata,
+    ctx: &JobContext,
+) -> Result<Option<Difference>, Error> {
+    ctx.file_done();
+    if left_meta.file_type().is_symlink() || right_meta.file_type().is_symlink() {
+        let same = fs::read_link(left).ok() == fs::read_link(right).ok();
+        return Ok(if same {
+            None
+        } else {
+            Some(Difference::Content)
+        });
+    }
+    if left_meta.len() != right_meta.len() {
+        return Ok(Some(Difference::Size));
+    }
+    if left_meta.mtime() == right_meta.mtime() {
+        return Ok(None);
+    }
+    ctx.add_total(left_meta.len(), 0);
+    Ok(if same_content(left, right, ctx)? {
+        Some(Difference::Modified)
+    } else {
+        Some(Difference::Content)
+    })
+}
+
+fn same_content(left: &Path, right: &Path, ctx: &JobContext) -> Result<bool, Error> {
+    let (mut left, mut right) = (File::open(left)?, File::open(right)?);
+    let mut left_buffer = vec![0; 64 * 1024];
+    let mut right_buffer = vec![0; 64 * 1024];
+    loop {
+        ctx.checkpoint()?;
+        let read = read_full(&mut left, &mut left_buffer)?;
+        if read != read_full(&mut right, &mut right_buffer)? {
+            return Ok(false);
+        }
+        if read == 0 {
+            return Ok(true);
+        }
+        if left_buffer[..read] != right_buffer[..read] {
+            return Ok(false);
+        }
+        ctx.advance(read as u64);
+    }
+}
+
+// reads until the buffer is full or the file ends
+fn read_full(file: &mut File, buffer: &mut [u8]) -> Result<usize, Error> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match file.read(&mut buffer[filled..])? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+    Ok(filled)
+}
@@ -2,9 +2,11 @@ use std::path::{Path, PathBuf};
 use termion::event::Key;
 use toml::Value;
 
-use crate::util::{Filter, SortBy};
+use crate::util::{home_dir, Filter, SortBy};
 use crate::{
+    file_ops::LinkKind,
     keys::{Action, Keybind},
+    shell::{ExecMode, ShellCommand, ShowOutput},
     util::{PaneConfig, PaneRole},
 };
 
@@ -17,11 +19,15 @@ use crate::{
 pub struct Config {
     pub keybindings: Vec<Keybind>,
     pub panes: Vec<PaneConfig>,
+    // (extension, template): new files start as a copy of the template
+    pub templates: Vec<(String, PathBuf)>,
 }
 
 impl Config {
-    pub fn new() -> Option<Self> {
-        let config_str = get_config_string()?;
+    // loads the config from the given file or, if none is given, from the
+    // first config file found in the default locations
+    pub fn new(path: Option<&Path>) -> Option<Self> {
+        let config_str = get_config_string(path)?;
         let values = config_str.parse::<Value>().ok()?;
         let keys_table = values["keys"].as_table()?;
         let mut keybindings = Vec::new();
@@ -64,18 +70,63 @@ impl Config {
             action: Action::TUICmd("nvim".to_string()),
         };
         keybindings.push(vim_bind);
-        Some(Config { keybindings, panes })
+        let mut templates = Vec::new();
+        if let Some(templates_table) = values.get("templates").and_then(|t| t.as_table()) {
+            for (extension, template) in templates_table {
+                if let Some(template) = template.as_str().and_then(parse_jump_command) {
+                    templates.push((extension.clone(), template));
+                }
+            }
+        }
+        Some(Config {
+            keybindings,
+            panes,
+            templates,
+        })
+    }
+
+    // the template for a new file, chosen by its extension
+    pub fn template_for(&self, name: &str) -> Option<&Path> {
+        let extension = Path::new(name).extension()?.to_str()?;
+        self.templates
+            .iter()
+            .find(|(template_ext, _)| template_ext == extension)
+            .map(|(_, template)| template.as_path())
+    }
+
+    // the action bound to the single key, for popups that are navigated
+    // like the listing
+    pub fn action_for(&self, key: Key) -> Option<&Action> {
+        self.keybindings
+            .iter()
+            .find(|keybind| keybind.keys == [key])
+            .map(|keybind| &keybind.action)
+    }
+}
+
+fn get_config_string(path: Option<&Path>) -> Option<String> {
+    if let Some(path) = path {
+        return std::fs::read_to_string(path).ok();
     }
+    config_paths()
+        .iter()
+        .find_map(|path| std::fs::read_to_string(path).ok())
 }
 
-fn get_config_string() -> Option<String> {
-    // TODO add different (not hard-coded) config paths  search for
-    // this is a temporary solution but should work on most systems
-    let cu_exe = std::env::current_exe().ok()?;
-    let mut path = Path::new(&cu_exe);
-    path = path.parent()?.parent()?.parent()?;
-    let path = path.join("Config.toml");
-    Some(std::fs::read_to_string(path).ok()?)
+// the default config locations in the order they are searched in
+fn config_paths() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    if let Ok(xdg_config) = std::env::var("XDG_CONFIG_HOME") {
+        paths.push(Path::new(&xdg_config).join("mampf").join("Config.toml"));
+    }
+    paths.push(home_dir().join(".config").join("mampf").join("Config.toml"));
+    // the example config in the repository, so that 'cargo run' keeps working
+    if let Ok(cu_exe) = std::env::current_exe() {
+        if let Some(path) = cu_exe.parent().and_then(|p| p.parent()?.parent()) {
+            paths.push(path.join("Config.toml"));
+        }
+    }
+    paths
 }
 
 // from here on there are only helpers functions to parse a possible keybind
@@ -87,24 +138,43 @@ fn parse_one_app_keybind(t: &Value) -> Option<Keybind> {
     let arr = t.as_array()?;
     let action = arr.get(1)?.clone().try_into::<String>().ok()?;
     let action = parse_app_command(&action)?;
-    let keys = arr.get(0)?.clone().try_into::<String>().ok()?;
+    let keys = arr.first()?.clone().try_into::<String>().ok()?;
     let keys = parse_keys(&keys)?;
     Keybind::from(keys, action)
 }
 
+// a command binding can have options as third element, separated by
+// spaces, e.g. ["M-g", "grep -n TODO %f | less", "shell"]
 fn parse_one_cmd_keybind(t: &Value) -> Option<Keybind> {
     let arr = t.as_array()?;
     let action = arr.get(1)?.clone().try_into::<String>().ok()?;
-    let keys = arr.get(0)?.clone().try_into::<String>().ok()?;
+    let mut cmd = ShellCommand::new(&action);
+    if let Some(options) = arr.get(2).and_then(|options| options.as_str()) {
+        for option in options.split_whitespace() {
+            match option {
+                "shell" => cmd.shell = true,
+                "output=silent" => cmd.output = ShowOutput::Never,
+                "output=error" => cmd.output = ShowOutput::OnError,
+                "output=always" => cmd.output = ShowOutput::Always,
+                "mode=tui" => cmd.mode = ExecMode::Tui,
+                "mode=blocking" => cmd.mode = ExecMode::Blocking,
+                "mode=background" => cmd.mode = ExecMode::Background,
+                "mode=detached" => cmd.mode = ExecMode::Detached,
+                // unknown options are ignored like invalid keys
+                _ => {}
+            }
+        }
+    }
+    let keys = arr.first()?.clone().try_into::<String>().ok()?;
     let keys = parse_keys(&keys)?;
-    Keybind::from(keys, Action::ShellCmd(action))
+    Keybind::from(keys, Action::ShellCmd(cmd))
 }
 
 fn parse_one_jump_keybind(t: &Value) -> Option<Keybind> {
     let arr = t.as_array()?;
     let action = arr.get(1)?.clone().try_into::<String>().ok()?;
     let pathb = parse_jump_command(&action)?;
-    let keys = arr.get(0)?.clone().try_into::<String>().ok()?;
+    let keys = arr.first()?.clone().try_into::<String>().ok()?;
     let keys = parse_keys(&keys)?;
     Keybind::from(keys, Action::Jump(pathb))
 }
@@ -116,7 +186,7 @@ fn parse_keys(keys: &str) -> Option<Vec<Key>> {
 
     for key_str in keys_strs {
         // invalid keys are just ignored for now
-        let key = parse_one_key(&key_str.to_string())?;
+        let key = parse_one_key(key_str)?;
         keys.push(key);
     }
     if keys.is_empty() {
@@ -153,7 +223,7 @@ fn parse_one_key(key: &str) -> Option<Key> {
                         None
                     } else {
                         let second_char = temp.next()?;
-                        if second_char.is_digit(10) {
+                        if second_char.is_ascii_digit() {
                             // 48 is ascii offset for numbers
                             let num = second_char.to_digit(10)? as u8;
                             Some(Key::F(num))
@@ -183,28 +253,69 @@ fn parse_one_key(key: &str) -> Option<Key> {
     }
 }
 
-fn parse_app_command(cmd: &str) -> Option<Action> {
-    match cmd.to_lowercase().as_str() {
-        "up" => Some(Action::Up),
-        "down" => Some(Action::Down),
-        "in" => Some(Action::In),
-        "out" => Some(Action::Out),
-        "quit" => Some(Action::Quit),
-        "mark" => Some(Action::Mark),
-        "unmark" => Some(Action::UnMark),
-        "markall" => Some(Action::MarkAll),
-        "unmarkall" => Some(Action::UnMarkAll),
-        "toggledotfiles" => Some(Action::ToggleFilter(Filter::Dotfiles)),
-        "sortbyinc" => Some(Action::DoSortBy(SortBy::LexioInc)),
-        "sortbydec" => Some(Action::DoSortBy(SortBy::LexioDec)),
-        "sortbynew" => Some(Action::DoSortBy(SortBy::New)),
-        _ => None,
-    }
+// the names of all actions that can be bound in the app table (and typed
+// on the command line)
+pub const APP_COMMANDS: [(&str, Action); 47] = [
+    ("up", Action::Up),
+    ("down", Action::Down),
+    ("in", Action::In),
+    ("out", Action::Out),
+    ("quit", Action::Quit),
+    ("quitnocd", Action::QuitNoCd),
+    ("choose", Action::Choose),
+    ("mark", Action::Mark),
+    ("unmark", Action::UnMark),
+    ("markall", Action::MarkAll),
+    ("unmarkall", Action::UnMarkAll),
+    ("copy", Action::Copy),
+    ("move", Action::Move),
+    ("yank", Action::Yank),
+    ("cut", Action::Cut),
+    ("paste", Action::Paste),
+    ("register", Action::SelectRegister),
+    ("delete", Action::Delete),
+    ("trash", Action::Trash),
+    ("showtrash", Action::ShowTrash),
+    ("rename", Action::Rename),
+    ("bulkrename", Action::BulkRename),
+    ("mkdir", Action::Mkdir),
+    ("touch", Action::Touch),
+    ("chmod", Action::Chmod),
+    ("extract", Action::Extract),
+    ("compress", Action::Compress),
+    ("dirsizes", Action::DirSizes),
+    ("diskusage", Action::DiskUsage),
+    ("duplicates", Action::Duplicates),
+    ("compare", Action::Compare),
+    ("checksum", Action::Checksum),
+    ("verify", Action::VerifyChecksums),
+    ("output", Action::ShowOutput),
+    ("commandline", Action::CommandLine),
+    ("togglecrossfs", Action::ToggleCrossFs),
+    ("symlink", Action::Link(LinkKind::Absolute)),
+    ("relsymlink", Action::Link(LinkKind::Relative)),
+    ("hardlink", Action::Link(LinkKind::Hard)),
+    ("jobs", Action::Jobs),
+    ("undo", Action::Undo),
+    ("redo", Action::Redo),
+    ("toggledotfiles", Action::ToggleFilter(Filter::Dotfiles)),
+    ("sortbyinc", Action::DoSortBy(SortBy::LexioInc)),
+    ("sortbydec", Action::DoSortBy(SortBy::LexioDec)),
+    ("sortbynew", Action::DoSortBy(SortBy::New)),
+    ("sortbysize", Action::DoSortBy(SortBy::Size)),
+];
+
+pub fn parse_app_command(cmd: &str) -> Option<Action> {
+    let cmd = cmd.to_lowercase();
+    APP_COMMANDS
+        .iter()
+        .find(|(name, _)| *name == cmd)
+        .map(|(_, action)| action.clone())
 }
 
-fn parse_jump_command(jmp: &str) -> Option<PathBuf> {
-    let home_dir = std::env::var("HOME").ok()?;
-    let home_dir = home_dir.as_str();
+pub fn parse_jump_command(jmp: &str) -> Option<PathBuf> {
+    let home_dir = home_dir();
+    let home_dir = home_dir.to_str()?;
     let mut jmp = jmp.replace("~", home_dir);
     jmp = jmp.replace("$HOME", home_dir);
     let pathb = PathBuf::from(jmp);
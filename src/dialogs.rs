@@ -0,0 +1,1364 @@
+use std::{
+    collections::HashSet,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use termion::event::Key;
+
+use crate::{
+    app::App,
+    bulk_rename, checksum, command_line,
+    compare::{self, Difference},
+    config, du, duplicates,
+    file_ops::{self, Attributes, LinkKind, Transfer},
+    jobs::JobContext,
+    journal::Operation,
+    keys::Action,
+    trash,
+    ui::{terminal_ui::TerminalUI, UI},
+    util::{
+        copy_to_clipboard, format_bytes, format_time, get_modified, group_id, group_name, user_id,
+        user_name, EntryStyle, Popup,
+    },
+    vfs,
+};
+
+// Dialogs are small modal loops on top of the main loop: they draw a popup,
+// handle their own keys and return once the user is done with them
+
+// how often dialogs redraw while waiting for a key, e.g. to update progress
+const TICK: Duration = Duration::from_millis(250);
+
+// how far pages scroll in long popups
+const PAGE: usize = 10;
+
+// moves the selection of a list with the usual keys, returns whether the
+// key was handled
+fn move_selection(key: Key, selected: &mut usize, len: usize) -> bool {
+    match key {
+        Key::Char('j') | Key::Down if len > 0 => *selected = (*selected + 1) % len,
+        Key::Char('k') | Key::Up if len > 0 => *selected = (*selected + len - 1) % len,
+        _ => return false,
+    }
+    true
+}
+
+// lists all background jobs with their progress, the selected job can be
+// paused (p), resumed (r) and cancelled (c)
+pub fn show_jobs(state: &mut App, ui: &mut impl UI) -> Result<(), std::io::Error> {
+    let mut selected = 0;
+    loop {
+        state.update_jobs();
+        let jobs = &state.jobs.jobs;
+        selected = selected.min(jobs.len().saturating_sub(1));
+        let mut lines = jobs
+            .iter()
+            .map(|job| {
+                let line = format!("{:>3}% {}", (job.ratio() * 100.0) as u8, job.describe());
+                let style = if job.is_paused() {
+                    EntryStyle::Gray
+                } else {
+                    EntryStyle::Cyan
+                };
+                (line, style)
+            })
+            .collect::<Vec<(String, EntryStyle)>>();
+        if lines.is_empty() {
+            lines.push((String::from("no running jobs"), EntryStyle::Gray));
+        }
+        state.popup = Some(Popup {
+            title: String::from(" jobs: p pause, r resume, c cancel, q close "),
+            lines,
+            selected: if jobs.is_empty() {
+                None
+            } else {
+                Some(selected)
+            },
+        });
+        ui.refresh(state)?;
+        let key = match ui.poll_keypress(Some(TICK)) {
+            Some(key) => key,
+            None => continue,
+        };
+        if move_selection(key, &mut selected, state.jobs.jobs.len()) {
+            continue;
+        }
+        let job = state.jobs.jobs.get(selected);
+        match key {
+            Key::Char('p') => job.iter().for_each(|job| job.pause()),
+            Key::Char('r') => job.iter().for_each(|job| job.resume()),
+            Key::Char('c') => job.iter().for_each(|job| job.cancel()),
+            Key::Char('q') | Key::Esc => break,
+            _ => {}
+        }
+    }
+    state.popup = None;
+    Ok(())
+}
+
+// picks the selection in --choosefiles mode and quits, which has to be
+// confirmed like any quit while jobs are running
+pub fn choose(state: &mut App, ui: &mut impl UI) -> Result<(), std::io::Error> {
+    if state.choose() {
+        if confirm_quit(state, ui)? {
+            state.fm_state.exit();
+        } else {
+            state.chosen.clear();
+        }
+    }
+    Ok(())
+}
+
+// asks before quitting while jobs are still running, the jobs get
+// cancelled if the user wants to quit anyway
+pub fn confirm_quit(state: &mut App, ui: &mut impl UI) -> Result<bool, std::io::Error> {
+    if !state.jobs.has_unfinished() {
+        return Ok(true);
+    }
+    let question = format!(
+        "{} jobs are still running. Cancel them and quit?",
+        state.jobs.unfinished()
+    );
+    if ui.confirm(state, &question)? {
+        // give the jobs a moment to clean up half copied files
+        state.jobs.cancel_all();
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while state.jobs.has_unfinished() && std::time::Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        Ok(true)
+    } else {
+        Ok(false)
+    }
+}
+
+// lists the items in the trash with their original path and deletion date,
+// the selected item can be restored (r) or deleted for good (d)
+pub fn show_trash(state: &mut App, ui: &mut impl UI) -> Result<(), std::io::Error> {
+    let mut items = trash::list();
+    let mut selected = 0;
+    loop {
+        selected = selected.min(items.len().saturating_sub(1));
+        let mut lines = items
+            .iter()
+            .map(|item| {
+                let date = item.deleted.replace('T', " ");
+                let line = format!("{}  {}", date, item.original.display());
+                (line, EntryStyle::Blue)
+            })
+            .collect::<Vec<(String, EntryStyle)>>();
+        if lines.is_empty() {
+            lines.push((String::from("the trash is empty"), EntryStyle::Gray));
+        }
+        state.popup = Some(Popup {
+            title: String::from(" trash: r restore, d delete forever, q close "),
+            lines,
+            selected: if items.is_empty() {
+                None
+            } else {
+                Some(selected)
+            },
+        });
+        ui.refresh(state)?;
+        let key = ui.get_next_keypress();
+        if move_selection(key, &mut selected, items.len()) {
+            continue;
+        }
+        match key {
+            Key::Char('r') if !items.is_empty() => {
+                let item = &items[selected];
+                state.message = Some(match trash::restore(item) {
+                    Ok(()) => format!("restored {}", item.original.display()),
+                    Err(error) => format!("restore failed: {}", error),
+                });
+                items = trash::list();
+                du::invalidate();
+            }
+            Key::Char('d') if !items.is_empty() => {
+                let item = items[selected].clone();
+                let question = format!("Delete {} forever?", item.original.display());
+                state.popup = None;
+                if ui.confirm(state, &question)? {
+                    state.message = Some(match trash::purge(&item) {
+                        Ok(()) => format!("deleted {}", item.original.display()),
+                        Err(error) => format!("delete failed: {}", error),
+                    });
+                    items = trash::list();
+                }
+            }
+            Key::Char('q') | Key::Esc => break,
+            _ => {}
+        }
+    }
+    state.popup = None;
+    state.fm_state.refocus();
+    Ok(())
+}
+
+// like ncdu, shows what takes up the space below the current directory:
+// the entries sorted by their size with a bar of their share. It is moved
+// through with the keys of up, down, in (or enter) and out, the key of
+// delete deletes the selected entry in the background
+pub fn disk_usage(state: &mut App, ui: &mut impl UI) -> Result<(), std::io::Error> {
+    let root = state.fm_state.get_currentdir();
+    if !root.is_dir() {
+        state.message = Some(String::from("disk usage only works on real directories"));
+        return Ok(());
+    }
+    let mut dir = root.clone();
+    let mut selected = 0;
+    // the selections in the directories above, restored on the way back
+    let mut trail = Vec::new();
+    // entries whose delete job is running, they are gone for the usage
+    let mut deleting = HashSet::new();
+    loop {
+        let total = du::usage(&dir);
+        if total.is_none() {
+            du::request(&dir);
+        }
+        let mut entries = usage_entries(&dir, total.is_some());
+        entries.retain(|entry| !deleting.contains(&entry.path));
+        selected = selected.min(entries.len().saturating_sub(1));
+        let (title, lines) = match total {
+            None => (
+                format!(" disk usage of {} ", dir.display()),
+                vec![(
+                    format!("scanning, {} entries so far", du::progress()),
+                    EntryStyle::Gray,
+                )],
+            ),
+            Some(total) => (
+                format!(
+                    " {} in {}: in/out browse, delete deletes, q close ",
+                    format_bytes(total.bytes),
+                    dir.display()
+                ),
+                usage_lines(&entries, total.bytes),
+            ),
+        };
+        state.popup = Some(Popup {
+            title,
+            lines,
+            selected: if entries.is_empty() {
+                None
+            } else {
+                Some(selected)
+            },
+        });
+        ui.refresh(state)?;
+        let key = match ui.poll_keypress(Some(TICK)) {
+            Some(key) => key,
+            None => continue,
+        };
+        let entry = entries.get(selected);
+        let action = match key {
+            Key::Char('\n') => Some(Action::In),
+            Key::Char('q') | Key::Esc => break,
+            key => state.config.action_for(key).cloned(),
+        };
+        let len = entries.len();
+        match action {
+            Some(Action::Down) if len > 0 => selected = (selected + 1) % len,
+            Some(Action::Up) if len > 0 => selected = (selected + len - 1) % len,
+            Some(Action::In) => {
+                if let Some(entry) = entry.filter(|entry| entry.is_dir) {
+                    trail.push(selected);
+                    dir = entry.path.clone();
+                    selected = 0;
+                }
+            }
+            Some(Action::Out) if dir != root => {
+                dir = dir.parent().map(Path::to_path_buf).unwrap_or(dir);
+                selected = trail.pop().unwrap_or(0);
+            }
+            Some(Action::Delete) => {
+                if let Some(entry) = entry.cloned() {
+                    let question = format!("Delete {} forever?", entry.path.display());
+                    state.popup = None;
+                    if ui.confirm(state, &question)? {
+                        // the sizes shrink right away, a failure shows up
+                        // once the job is done (and everything is rescanned)
+                        state.delete_paths(vec![entry.path.clone()]);
+                        du::forget(&entry.path, entry.usage);
+                        state.fm_state.unmark(&entry.path);
+                        deleting.insert(entry.path);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    state.popup = None;
+    state.fm_state.refocus();
+    Ok(())
+}
+
+// an entry of the disk usage view
+#[derive(Debug, Clone)]
+struct UsageEntry {
+    path: PathBuf,
+    is_dir: bool,
+    usage: du::Usage,
+    // false for directories that weren't scanned, like other file systems
+    counted: bool,
+}
+
+// the entries of dir, largest first. Nothing is listed before dir is scanned
+fn usage_entries(dir: &Path, scanned: bool) -> Vec<UsageEntry> {
+    if !scanned {
+        return Vec::new();
+    }
+    let mut entries = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            let path = entry.path();
+            let (usage, counted) = if metadata.is_dir() {
+                match du::usage(&path) {
+                    Some(usage) => (usage, true),
+                    None => (du::Usage::default(), false),
+                }
+            } else {
+                let usage = du::Usage {
+                    bytes: metadata.len(),
+                    items: 0,
+                };
+                (usage, true)
+            };
+            Some(UsageEntry {
+                path,
+                is_dir: metadata.is_dir(),
+                usage,
+                counted,
+            })
+        })
+        .collect::<Vec<UsageEntry>>();
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.usage.bytes));
+    entries
+}
+
+// e.g. "  12MB  40.2% [########            ]   1520 items  src/"
+fn usage_lines(entries: &[UsageEntry], total: u64) -> Vec<(String, EntryStyle)> {
+    const BAR: usize = 20;
+    if entries.is_empty() {
+        return vec![(String::from("empty directory"), EntryStyle::Gray)];
+    }
+    entries
+        .iter()
+        .map(|entry| {
+            let share = if total > 0 {
+                entry.usage.bytes as f64 / total as f64
+            } else {
+                0.0
+            };
+            let filled = ((share * BAR as f64).round() as usize).min(BAR);
+            let bar = "#".repeat(filled) + &" ".repeat(BAR - filled);
+            let name = entry.path.file_name().unwrap_or_default().to_string_lossy();
+            let (items, name, style) = match (entry.is_dir, entry.counted) {
+                (true, true) => (
+                    format!("{} items", entry.usage.items),
+                    format!("{}/", name),
+                    EntryStyle::Cyan,
+                ),
+                (true, false) => (
+                    String::from("not counted"),
+                    format!("{}/", name),
+                    EntryStyle::Gray,
+                ),
+                (false, _) => (String::new(), name.into_owned(), EntryStyle::Blue),
+            };
+            let line = format!(
+                "{:>7} {:>5.1}% [{}] {:>12}  {}",
+                format_bytes(entry.usage.bytes),
+                share * 100.0,
+                bar,
+                items,
+                name
+            );
+            (line, style)
+        })
+        .collect()
+}
+
+// searches the marked directories (or the current one) for identical files
+// and lists them grouped. Duplicates are marked with space (a marks all but
+// the first of every group), marked ones can be trashed (t), deleted (D) or
+// replaced by hardlinks to the first unmarked file of their group (l)
+pub fn find_duplicates(state: &mut App, ui: &mut impl UI) -> Result<(), std::io::Error> {
+    let mut roots = state
+        .fm_state
+        .get_marked()
+        .into_iter()
+        .filter(|pathb| pathb.is_dir())
+        .collect::<Vec<PathBuf>>();
+    if roots.is_empty() {
+        roots.push(state.fm_state.get_currentdir());
+    }
+    if roots.iter().any(|root| !root.is_dir()) {
+        state.message = Some(String::from("can't search archives for duplicates"));
+        return Ok(());
+    }
+    let search = move |ctx: &JobContext| duplicates::find(&roots, ctx);
+    let mut groups = match run_with_progress(state, ui, "duplicate search", search)? {
+        Some(groups) => groups,
+        None => return Ok(()),
+    };
+    // the duplicates marked in the popup, the marks of the listing stay
+    let mut marked = HashSet::new();
+    let mut selected = 0;
+    loop {
+        state.update_jobs();
+        // (group, path) of every line, None for the group headers
+        let mut rows = Vec::new();
+        let mut lines = Vec::new();
+        for (group_idx, group) in groups.iter().enumerate() {
+            let header = format!(
+                "{} files of {}",
+                group.paths.len(),
+                format_bytes(group.size)
+            );
+            lines.push((header, EntryStyle::Gray));
+            rows.push(None);
+            for (path_idx, pathb) in group.paths.iter().enumerate() {
+                let (line, style) = if marked.contains(pathb) {
+                    (format!("* {}", pathb.display()), EntryStyle::Yellow)
+                } else {
+                    (format!("  {}", pathb.display()), EntryStyle::Blue)
+                };
+                lines.push((line, style));
+                rows.push(Some((group_idx, path_idx)));
+            }
+        }
+        if lines.is_empty() {
+            lines.push((String::from("no duplicates found"), EntryStyle::Gray));
+        }
+        selected = selected.min(rows.len().saturating_sub(1));
+        state.popup = Some(Popup {
+            title: String::from(
+                " duplicates: space mark, a auto-mark, t trash, D delete, l hardlink, q close ",
+            ),
+            lines,
+            selected: if rows.is_empty() {
+                None
+            } else {
+                Some(selected)
+            },
+        });
+        ui.refresh(state)?;
+        let key = match ui.poll_keypress(Some(TICK)) {
+            Some(key) => key,
+            None => continue,
+        };
+        if move_selection(key, &mut selected, rows.len()) {
+            continue;
+        }
+        let chosen = groups
+            .iter()
+            .map(|group| {
+                group
+                    .paths
+                    .iter()
+                    .filter(|pathb| marked.contains(*pathb))
+                    .cloned()
+                    .collect::<Vec<PathBuf>>()
+            })
+            .collect::<Vec<Vec<PathBuf>>>();
+        // at least one file of every group has to stay
+        let keeps_one = groups
+            .iter()
+            .zip(chosen.iter())
+            .all(|(group, chosen)| chosen.len() < group.paths.len());
+        match key {
+            Key::Char(' ') => {
+                if let Some(Some((group_idx, path_idx))) = rows.get(selected) {
+                    let pathb = &groups[*group_idx].paths[*path_idx];
+                    if !marked.remove(pathb) {
+                        marked.insert(pathb.clone());
+                    }
+                }
+                selected = (selected + 1).min(rows.len().saturating_sub(1));
+            }
+            Key::Char('a') => {
+                for group in groups.iter() {
+                    group.paths.iter().skip(1).for_each(|pathb| {
+                        marked.insert(pathb.clone());
+                    });
+                }
+            }
+            Key::Char('t') | Key::Char('D') | Key::Char('l') if !keeps_one => {
+                state.message = Some(String::from("keep at least one file of every group"));
+            }
+            Key::Char('t') | Key::Char('D') | Key::Char('l')
+                if chosen.iter().all(Vec::is_empty) =>
+            {
+                state.message = Some(String::from("no duplicates marked"));
+            }
+            Key::Char('t') => {
+                state.trash_paths(chosen.concat());
+                drop_paths(&mut groups, &chosen);
+                marked.clear();
+            }
+            Key::Char('D') => {
+                let count = chosen.iter().map(Vec::len).sum::<usize>();
+                let question = format!("Delete {} duplicates forever?", count);
+                state.popup = None;
+                if ui.confirm(state, &question)? {
+                    state.delete_paths(chosen.concat());
+                    drop_paths(&mut groups, &chosen);
+                    marked.clear();
+                }
+            }
+            Key::Char('l') => {
+                let mut transfers = Vec::new();
+                for (group, chosen) in groups.iter().zip(chosen.iter()) {
+                    let keep = group.paths.iter().find(|pathb| !chosen.contains(pathb));
+                    if let Some(keep) = keep {
+                        transfers.extend(chosen.iter().map(|duplicate| Transfer {
+                            source: keep.clone(),
+                            dest: duplicate.clone(),
+                            replace: true,
+                        }));
+                    }
+                }
+                state.link_transfers(transfers, LinkKind::Hard);
+                drop_paths(&mut groups, &chosen);
+                marked.clear();
+            }
+            Key::Char('q') | Key::Esc => break,
+            _ => {}
+        }
+    }
+    state.popup = None;
+    state.fm_state.refocus();
+    Ok(())
+}
+
+// compares the current directory with another one (asked for, enter alone
+// takes the last one) and lists the differences. Differing entries are
+// marked with space, > copies the marked (or the selected) ones to the
+// other directory and < copies them from there. Replaced files are trashed
+pub fn compare_dirs(state: &mut App, ui: &mut impl UI) -> Result<(), std::io::Error> {
+    let question = match &state.other_dir {
+        Some(other_dir) => format!("Compare with ({}): ", other_dir.display()),
+        None => String::from("Compare with: "),
+    };
+    let input = ui.get_user_input(state, &question)?;
+    let left = state.fm_state.get_currentdir();
+    let right = if input.is_empty() {
+        match &state.other_dir {
+            Some(other_dir) => other_dir.clone(),
+            None => return Ok(()),
+        }
+    } else {
+        // relative paths start in the current directory
+        let input = if input.starts_with('~') || input.starts_with('$') {
+            input
+        } else {
+            left.join(&input).to_string_lossy().into_owned()
+        };
+        match config::parse_jump_command(&input).and_then(|pathb| pathb.canonicalize().ok()) {
+            Some(pathb) => pathb,
+            None => {
+                state.message = Some(format!("{} doesn't exist", input));
+                return Ok(());
+            }
+        }
+    };
+    if !left.is_dir() || !right.is_dir() {
+        state.message = Some(String::from("only real directories can be compared"));
+        return Ok(());
+    }
+    if left == right {
+        state.message = Some(String::from("that is the current directory"));
+        return Ok(());
+    }
+    state.other_dir = Some(right.clone());
+    let (thread_left, thread_right) = (left.clone(), right.clone());
+    let comparison = move |ctx: &JobContext| compare::compare(&thread_left, &thread_right, ctx);
+    let mut entries = match run_with_progress(state, ui, "comparison", comparison)? {
+        Some(entries) => entries,
+        None => return Ok(()),
+    };
+    let mut marked = HashSet::new();
+    let mut selected = 0;
+    loop {
+        state.update_jobs();
+        selected = selected.min(entries.len().saturating_sub(1));
+        let mut lines = entries
+            .iter()
+            .map(|entry| {
+                let mark = if marked.contains(&entry.path) {
+                    "*"
+                } else {
+                    " "
+                };
+                let line = format!(
+                    "{} {:<16} {}",
+                    mark,
+                    entry.difference.describe(),
+                    entry.path.display()
+                );
+                let style = match entry.difference {
+                    Difference::LeftOnly => EntryStyle::Green,
+                    Difference::RightOnly => EntryStyle::Magenta,
+                    _ => EntryStyle::Yellow,
+                };
+                (line, style)
+            })
+            .collect::<Vec<(String, EntryStyle)>>();
+        if lines.is_empty() {
+            lines.push((String::from("no differences"), EntryStyle::Gray));
+        }
+        state.popup = Some(Popup {
+            title: format!(
+                " compared with {}: space mark, > copy there, < copy here, q close ",
+                right.display()
+            ),
+            lines,
+            selected: if entries.is_empty() {
+                None
+            } else {
+                Some(selected)
+            },
+        });
+        ui.refresh(state)?;
+        let key = match ui.poll_keypress(Some(TICK)) {
+            Some(key) => key,
+            None => continue,
+        };
+        if move_selection(key, &mut selected, entries.len()) {
+            continue;
+        }
+        match key {
+            Key::Char(' ') => {
+                if let Some(entry) = entries.get(selected) {
+                    if !marked.remove(&entry.path) {
+                        marked.insert(entry.path.clone());
+                    }
+                }
+                selected = (selected + 1).min(entries.len().saturating_sub(1));
+            }
+            Key::Char('>') | Key::Char('<') => {
+                let (from, to, missing) = if key == Key::Char('>') {
+                    (&left, &right, Difference::RightOnly)
+                } else {
+                    (&right, &left, Difference::LeftOnly)
+                };
+                let chosen = entries
+                    .iter()
+                    .enumerate()
+                    .filter(|(idx, entry)| {
+                        marked.contains(&entry.path) || (marked.is_empty() && *idx == selected)
+                    })
+                    .filter(|(_, entry)| entry.difference != missing)
+                    .map(|(_, entry)| entry.clone())
+                    .collect::<Vec<compare::Entry>>();
+                if chosen.is_empty() {
+                    continue;
+                }
+                let transfers = chosen
+                    .iter()
+                    .map(|entry| Transfer {
+                        source: from.join(&entry.path),
+                        dest: to.join(&entry.path),
+                        // only entries missing on the other side aren't replaced
+                        replace: !matches!(
+                            entry.difference,
+                            Difference::LeftOnly | Difference::RightOnly
+                        ),
+                    })
+                    .collect();
+                state.copy_transfers(transfers);
+                entries.retain(|entry| !chosen.iter().any(|c| c.path == entry.path));
+                marked.clear();
+            }
+            Key::Char('q') | Key::Esc => break,
+            _ => {}
+        }
+    }
+    state.popup = None;
+    state.fm_state.refocus();
+    Ok(())
+}
+
+// shows the checksums of the marked (or the focused) files, y copies the
+// selected one, Y all of them in the format of sha256sum
+pub fn show_checksums(state: &mut App, ui: &mut impl UI) -> Result<(), std::io::Error> {
+    let paths = state.fm_state.get_selection();
+    if paths.is_empty() {
+        return Ok(());
+    }
+    if paths.iter().any(|pathb| vfs::is_virtual(pathb)) {
+        state.message = Some(String::from("can't checksum files inside archives"));
+        return Ok(());
+    }
+    let computation = move |ctx: &JobContext| Ok(checksum::compute(&paths, ctx));
+    let results = match run_with_progress(state, ui, "checksums", computation)? {
+        Some(results) => results,
+        None => return Ok(()),
+    };
+    // the checksum of every line, None for the file names and errors
+    let mut rows = Vec::new();
+    let mut lines = Vec::new();
+    for (pathb, result) in results.iter() {
+        let name = pathb.file_name().unwrap_or_default().to_string_lossy();
+        match result {
+            Ok(sums) => {
+                lines.push((name.into_owned(), EntryStyle::Gray));
+                rows.push(None);
+                for (algorithm, sum) in checksum::ALGORITHMS.iter().zip(sums.iter()) {
+                    lines.push((
+                        format!("  {:<6} {}", algorithm.name(), sum),
+                        EntryStyle::Blue,
+                    ));
+                    rows.push(Some(sum.clone()));
+                }
+            }
+            Err(error) => {
+                lines.push((format!("{}: {}", name, error), EntryStyle::Red));
+                rows.push(None);
+            }
+        }
+    }
+    // sha256sum's format, to be pasted into a SHA256SUMS file
+    let all = results
+        .iter()
+        .filter_map(|(pathb, result)| {
+            let name = pathb.file_name()?.to_string_lossy();
+            Some(format!("{}  {}\n", result.as_ref().ok()?.first()?, name))
+        })
+        .collect::<String>();
+    let mut selected = rows.iter().position(Option::is_some).unwrap_or(0);
+    loop {
+        state.popup = Some(Popup {
+            title: String::from(" checksums: y copy, Y copy all as sha256sums, q close "),
+            lines: lines.clone(),
+            selected: Some(selected),
+        });
+        ui.refresh(state)?;
+        let key = match ui.poll_keypress(Some(TICK)) {
+            Some(key) => key,
+            None => continue,
+        };
+        if move_selection(key, &mut selected, lines.len()) {
+            continue;
+        }
+        let text = match key {
+            Key::Char('y') => rows[selected].clone(),
+            Key::Char('Y') if !all.is_empty() => Some(all.clone()),
+            Key::Char('q') | Key::Esc => break,
+            _ => None,
+        };
+        if let Some(text) = text {
+            state.message = Some(match copy_to_clipboard(&text) {
+                Ok(()) => String::from("copied to the clipboard"),
+                Err(error) => format!("copying failed: {}", error),
+            });
+        }
+    }
+    state.popup = None;
+    state.fm_state.refocus();
+    Ok(())
+}
+
+// checks the files listed in the focused checksum file (or the first one
+// of checksum::SUMS_FILES in the current directory), the files that don't
+// match are marked
+pub fn verify_checksums(state: &mut App, ui: &mut impl UI) -> Result<(), std::io::Error> {
+    let is_sums_file = |pathb: &Path| {
+        let name = pathb.file_name().unwrap_or_default().to_string_lossy();
+        checksum::SUMS_FILES.contains(&name.as_ref())
+            || name.ends_with(".sha256")
+            || name.ends_with(".md5")
+    };
+    let currentdir = state.fm_state.get_currentdir();
+    let sums_file = state
+        .fm_state
+        .get_focused()
+        .filter(|pathb| is_sums_file(pathb) && pathb.is_file())
+        .or_else(|| {
+            checksum::SUMS_FILES
+                .iter()
+                .map(|name| currentdir.join(name))
+                .find(|pathb| pathb.is_file())
+        });
+    let sums_file = match sums_file {
+        Some(sums_file) => sums_file,
+        None => {
+            state.message = Some(String::from("no checksum file in the current dir"));
+            return Ok(());
+        }
+    };
+    let verification = move |ctx: &JobContext| checksum::verify(&sums_file, ctx);
+    let verification = match run_with_progress(state, ui, "verification", verification)? {
+        Some(verification) => verification,
+        None => return Ok(()),
+    };
+    let checked = verification.ok.len() + verification.failed.len() + verification.missing.len();
+    state.message = Some(
+        if verification.failed.is_empty() && verification.missing.is_empty() {
+            format!("all {} files are ok", checked)
+        } else {
+            state.fm_state.unmark_all();
+            verification
+                .failed
+                .iter()
+                .for_each(|pathb| state.fm_state.mark(pathb));
+            format!(
+                "checked {} files: {} failed (marked), {} missing",
+                checked,
+                verification.failed.len(),
+                verification.missing.len()
+            )
+        },
+    );
+    state.fm_state.refocus();
+    Ok(())
+}
+
+// asks for a command line and returns the action it stands for. Tab
+// completes the input (again for the next candidate), up and down go
+// through the previous commands
+pub fn command_line(state: &mut App, ui: &mut impl UI) -> Result<Option<Action>, std::io::Error> {
+    let currentdir = state.fm_state.get_currentdir();
+    let mut history = command_line::load_history();
+    let mut history_idx = history.len();
+    let mut completions: Vec<String> = Vec::new();
+    let mut completion_idx = 0;
+    let input = ui.get_user_input_with(state, ":", &mut |key, input| match key {
+        Key::Char('\t') | Key::BackTab => {
+            // several candidates are cycled through as long as the input
+            // isn't changed, otherwise the input gets completed anew
+            let cycling = completions.len() > 1 && completions.get(completion_idx) == Some(input);
+            if cycling && key == Key::BackTab {
+                completion_idx = (completion_idx + completions.len() - 1) % completions.len();
+            } else if cycling {
+                completion_idx = (completion_idx + 1) % completions.len();
+            } else {
+                completions = command_line::complete(input, &currentdir, &history);
+                completion_idx = 0;
+            }
+            if let Some(completion) = completions.get(completion_idx) {
+                *input = completion.clone();
+            }
+        }
+        Key::Up if history_idx > 0 => {
+            history_idx -= 1;
+            *input = history[history_idx].clone();
+        }
+        Key::Down if history_idx < history.len() => {
+            history_idx += 1;
+            *input = history.get(history_idx).cloned().unwrap_or_default();
+        }
+        _ => {}
+    })?;
+    let input = input.trim();
+    if input.is_empty() {
+        return Ok(None);
+    }
+    if let Err(error) = command_line::save_history(&mut history, input) {
+        state.message = Some(format!("can't save the history: {}", error));
+    }
+    match command_line::parse(input, &currentdir) {
+        Ok(action) => Ok(Some(action)),
+        Err(msg) => {
+            state.message = Some(msg);
+            Ok(None)
+        }
+    }
+}
+
+// shows what the last shell command printed, stderr in red
+pub fn show_output(state: &mut App, ui: &mut impl UI) -> Result<(), std::io::Error> {
+    let output = match &state.last_output {
+        Some(output) => output.clone(),
+        None => {
+            state.message = Some(String::from("no command ran yet"));
+            return Ok(());
+        }
+    };
+    let mut lines = output
+        .lines
+        .iter()
+        .map(|(line, is_stderr)| {
+            let style = if *is_stderr {
+                EntryStyle::Red
+            } else {
+                EntryStyle::Gray
+            };
+            (line.clone(), style)
+        })
+        .collect::<Vec<(String, EntryStyle)>>();
+    if lines.is_empty() {
+        lines.push((String::from("no output"), EntryStyle::Gray));
+    }
+    let title = format!(" {}: q close ", output.summary());
+    let mut selected = 0;
+    loop {
+        state.popup = Some(Popup {
+            title: title.clone(),
+            lines: lines.clone(),
+            selected: Some(selected),
+        });
+        ui.refresh(state)?;
+        let key = match ui.poll_keypress(Some(TICK)) {
+            Some(key) => key,
+            None => continue,
+        };
+        let last = lines.len() - 1;
+        match key {
+            Key::Char('j') | Key::Down => selected = (selected + 1).min(last),
+            Key::Char('k') | Key::Up => selected = selected.saturating_sub(1),
+            Key::Ctrl('d') | Key::PageDown => selected = (selected + PAGE).min(last),
+            Key::Ctrl('u') | Key::PageUp => selected = selected.saturating_sub(PAGE),
+            Key::Char('g') | Key::Home => selected = 0,
+            Key::Char('G') | Key::End => selected = last,
+            Key::Char('q') | Key::Esc => break,
+            _ => {}
+        }
+    }
+    state.popup = None;
+    Ok(())
+}
+
+// runs work on another thread while showing its progress, None if it
+// failed (the error goes into the status bar) or the user cancelled it
+fn run_with_progress<T, F>(
+    state: &mut App,
+    ui: &mut impl UI,
+    name: &str,
+    work: F,
+) -> Result<Option<T>, std::io::Error>
+where
+    T: Send + 'static,
+    F: FnOnce(&JobContext) -> Result<T, std::io::Error> + Send + 'static,
+{
+    let ctx = Arc::new(JobContext::default());
+    let result = Arc::new(Mutex::new(None));
+    let (thread_ctx, thread_result) = (ctx.clone(), result.clone());
+    std::thread::spawn(move || {
+        let done = work(&thread_ctx);
+        if let Ok(mut result) = thread_result.lock() {
+            *result = Some(done);
+        }
+    });
+    loop {
+        if let Some(done) = result.lock().ok().and_then(|mut result| result.take()) {
+            state.popup = None;
+            return match done {
+                Ok(done) => Ok(Some(done)),
+                Err(error) => {
+                    state.message = Some(format!("{} failed: {}", name, error));
+                    Ok(None)
+                }
+            };
+        }
+        let (bytes_done, bytes_total, files_done, files_total) = ctx.counts();
+        let mut line = files_done.to_string();
+        if files_total > 0 {
+            line += &format!("/{}", files_total);
+        }
+        line += &format!(" files, {}", format_bytes(bytes_done));
+        if bytes_total > 0 {
+            line += &format!("/{}", format_bytes(bytes_total));
+        }
+        state.popup = Some(Popup {
+            title: format!(" {}: q cancel ", name),
+            lines: vec![(line, EntryStyle::Gray)],
+            selected: None,
+        });
+        ui.refresh(state)?;
+        if let Some(Key::Char('q')) | Some(Key::Esc) = ui.poll_keypress(Some(TICK)) {
+            ctx.cancel();
+            state.popup = None;
+            return Ok(None);
+        }
+    }
+}
+
+// removes the paths from their groups, groups without duplicates left go
+fn drop_paths(groups: &mut Vec<duplicates::Group>, paths: &[Vec<PathBuf>]) {
+    for (group, paths) in groups.iter_mut().zip(paths.iter()) {
+        group.paths.retain(|pathb| !paths.contains(pathb));
+    }
+    groups.retain(|group| group.paths.len() > 1);
+}
+
+// renames the marked entries (or every entry of the current directory) by
+// editing their names in $EDITOR, the resulting plan has to be confirmed
+pub fn bulk_rename(state: &mut App, tui: &mut TerminalUI) -> Result<(), std::io::Error> {
+    let mut entries = state.fm_state.get_marked();
+    if entries.is_empty() {
+        entries = state.fm_state.list_current();
+    }
+    let text = match bulk_rename::names_text(&entries) {
+        Ok(text) => text,
+        Err(msg) => {
+            state.message = Some(format!("bulk rename: {}", msg));
+            return Ok(());
+        }
+    };
+    let names_file = bulk_rename::create_names_file(&text)?;
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| String::from("vi"));
+    // $EDITOR may come with arguments, the file name is never split
+    let edited = crate::split_command(&editor)
+        .and_then(|mut command| {
+            command.push(names_file.clone().into_os_string());
+            crate::run_tui(&command, tui)
+        })
+        .and_then(|()| std::fs::read_to_string(&names_file));
+    bulk_rename::remove_names_file(&names_file);
+    let renames = match edited {
+        Ok(edited) => bulk_rename::plan(&entries, &edited),
+        Err(error) => Err(format!("couldn't run '{}': {}", editor, error)),
+    };
+    let renames = match renames {
+        Ok(renames) if renames.is_empty() => {
+            state.message = Some(String::from("bulk rename: nothing changed"));
+            return Ok(());
+        }
+        Ok(renames) => renames,
+        Err(msg) => {
+            state.message = Some(format!("bulk rename: {}", msg));
+            return Ok(());
+        }
+    };
+
+    let lines = renames
+        .iter()
+        .map(|(from, to)| {
+            let from = from.file_name().unwrap_or_default().to_string_lossy();
+            let to = to.file_name().unwrap_or_default().to_string_lossy();
+            (format!("{}  ->  {}", from, to), EntryStyle::Yellow)
+        })
+        .collect();
+    state.popup = Some(Popup {
+        title: String::from(" bulk rename "),
+        lines,
+        selected: None,
+    });
+    let question = format!("Apply {} renames?", renames.len());
+    let confirmed = tui.confirm(state, &question)?;
+    state.popup = None;
+    if !confirmed {
+        return Ok(());
+    }
+    match bulk_rename::apply(&renames) {
+        Ok(()) => {
+            let focused = state.fm_state.get_focused();
+            for (from, to) in renames.iter() {
+                state.fm_state.unmark(from);
+                if focused.as_ref() == Some(from) {
+                    state.fm_state.focus(to);
+                }
+            }
+            let count = renames.len();
+            let description = format!("bulk rename of {} entries", count);
+            let operations = renames
+                .into_iter()
+                .map(|(from, to)| Operation::Move { from, to })
+                .collect();
+            state.journal.record(&description, operations);
+            du::invalidate();
+            state.message = Some(format!("renamed {}", count));
+        }
+        Err(error) => state.message = Some(format!("bulk rename failed: {}", error)),
+    }
+    state.fm_state.refocus();
+    Ok(())
+}
+
+// what to do with a source whose name is taken in the destination
+#[derive(Debug, Clone, Copy)]
+enum Resolution {
+    Overwrite,
+    Skip,
+    Rename,
+    OverwriteIfNewer,
+}
+
+// turns the sources into transfers into the current directory and asks
+// what to do whenever a name is taken already. Returns None if the user
+// cancelled or nothing is left to do
+pub fn resolve_conflicts(
+    state: &mut App,
+    ui: &mut impl UI,
+    sources: &[PathBuf],
+) -> Result<Option<Vec<Transfer>>, std::io::Error> {
+    let dest_dir = state.fm_state.get_currentdir();
+    let mut transfers = Vec::new();
+    let mut planned = HashSet::new();
+    let mut for_all = None;
+    let mut skipped = 0;
+    for (idx, source) in sources.iter().enumerate() {
+        let mut transfer = Transfer::into_dir(source, &dest_dir);
+        if planned.contains(&transfer.dest) {
+            // two sources with the same name, the later one gets a new name
+            let taken = |pathb: &Path| planned.contains(pathb) || pathb.symlink_metadata().is_ok();
+            transfer.dest = file_ops::free_name(&transfer.dest, taken);
+        } else if transfer.dest.symlink_metadata().is_ok() {
+            let resolution = match for_all {
+                Some(resolution) => resolution,
+                None => {
+                    let remaining = sources.len() - idx - 1;
+                    match ask_conflict(state, ui, &transfer, remaining)? {
+                        Some((resolution, true)) => *for_all.insert(resolution),
+                        Some((resolution, false)) => resolution,
+                        None => {
+                            state.popup = None;
+                            return Ok(None);
+                        }
+                    }
+                }
+            };
+            match resolution {
+                Resolution::Overwrite => transfer.replace = true,
+                Resolution::Rename => {
+                    let taken =
+                        |pathb: &Path| planned.contains(pathb) || pathb.symlink_metadata().is_ok();
+                    transfer.dest = file_ops::free_name(&transfer.dest, taken);
+                }
+                Resolution::OverwriteIfNewer
+                    if get_modified(&transfer.source) > get_modified(&transfer.dest) =>
+                {
+                    transfer.replace = true
+                }
+                Resolution::OverwriteIfNewer | Resolution::Skip => {
+                    skipped += 1;
+                    continue;
+                }
+            }
+        }
+        planned.insert(transfer.dest.clone());
+        transfers.push(transfer);
+    }
+    state.popup = None;
+    if skipped > 0 {
+        state.message = Some(format!("skipped {}", skipped));
+    }
+    Ok(if transfers.is_empty() {
+        None
+    } else {
+        Some(transfers)
+    })
+}
+
+// shows both sides of a conflict and returns the chosen resolution and
+// whether it applies to all remaining conflicts, None if cancelled
+fn ask_conflict(
+    state: &mut App,
+    ui: &mut impl UI,
+    transfer: &Transfer,
+    remaining: usize,
+) -> Result<Option<(Resolution, bool)>, std::io::Error> {
+    let name = transfer
+        .dest
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let dest_dir = transfer.dest.parent().unwrap_or_else(|| Path::new("/"));
+    let row =
+        |label: &str, source: String, dest: String| format!("{:<10}{:<28}{}", label, source, dest);
+    // sources might be inside of an archive
+    let size = |pathb: &Path| {
+        let (is_dir, len) = match pathb.symlink_metadata() {
+            Ok(metadata) => (metadata.is_dir(), metadata.len()),
+            Err(_) => match vfs::member(pathb) {
+                Some(member) => (member.is_dir, member.len),
+                None => return String::from("?"),
+            },
+        };
+        if is_dir {
+            format!("directory, {} entries", vfs::list(pathb).len())
+        } else {
+            format_bytes(len)
+        }
+    };
+    let modified = |pathb: &PathBuf| get_modified(pathb).map_or(String::from("?"), format_time);
+    let mut apply_to_all = false;
+    loop {
+        let mut lines = vec![
+            (
+                format!("{} already exists in {}", name, dest_dir.display()),
+                EntryStyle::Yellow,
+            ),
+            (String::new(), EntryStyle::Gray),
+            (
+                row("", String::from("source"), String::from("destination")),
+                EntryStyle::Gray,
+            ),
+            (
+                row("size", size(&transfer.source), size(&transfer.dest)),
+                EntryStyle::Blue,
+            ),
+            (
+                row(
+                    "modified",
+                    modified(&transfer.source),
+                    modified(&transfer.dest),
+                ),
+                EntryStyle::Blue,
+            ),
+        ];
+        if remaining > 0 {
+            let check = if apply_to_all { "x" } else { " " };
+            let line = format!("[{}] apply to the {} remaining entries", check, remaining);
+            lines.push((String::new(), EntryStyle::Gray));
+            lines.push((line, EntryStyle::Cyan));
+        }
+        state.popup = Some(Popup {
+            title: String::from(
+                " conflict: o overwrite, s skip, r rename, n overwrite if newer, a all, q cancel ",
+            ),
+            lines,
+            selected: None,
+        });
+        ui.refresh(state)?;
+        let resolution = match ui.get_next_keypress() {
+            Key::Char('o') => Resolution::Overwrite,
+            Key::Char('s') => Resolution::Skip,
+            Key::Char('r') => Resolution::Rename,
+            Key::Char('n') => Resolution::OverwriteIfNewer,
+            Key::Char('a') if remaining > 0 => {
+                apply_to_all = !apply_to_all;
+                continue;
+            }
+            Key::Char('q') | Key::Esc => return Ok(None),
+            _ => continue,
+        };
+        return Ok(Some((resolution, apply_to_all)));
+    }
+}
+
+// edits the mode bits of the marked entries (or the focused one) in a rwx
+// grid: j/k select a row, r/w/x toggle the bits of it (in the last row
+// setuid, setgid and sticky). o takes an octal mode, c a new owner
+pub fn edit_permissions(state: &mut App, ui: &mut impl UI) -> Result<(), std::io::Error> {
+    let targets = state.fm_state.get_selection();
+    let metadata = match targets.first().map(|first| first.symlink_metadata()) {
+        Some(Ok(metadata)) => metadata,
+        Some(Err(error)) => {
+            state.message = Some(format!("chmod: {}", error));
+            return Ok(());
+        }
+        None => return Ok(()),
+    };
+    let has_dirs = targets.iter().any(|target| {
+        target
+            .symlink_metadata()
+            .is_ok_and(|metadata| metadata.is_dir())
+    });
+    let initial = metadata.mode() & 0o7777;
+    let mut mode = initial;
+    // an octal mode sets every bit, toggling only the toggled ones
+    let mut octal_mode = false;
+    let mut attributes = Attributes::default();
+    let mut recursive = false;
+    let mut row = 0;
+    let check = |set: bool| if set { "[x]" } else { "[ ]" };
+    loop {
+        let bits = |shift: u32| {
+            let bit = |n: u32| check(mode & (1 << (shift + n)) != 0);
+            format!("{}    {}    {}", bit(2), bit(1), bit(0))
+        };
+        let mut heading = targets[0].display().to_string();
+        if targets.len() > 1 {
+            heading += &format!(" (+{} more)", targets.len() - 1);
+        }
+        let uid = attributes.uid.unwrap_or_else(|| metadata.uid());
+        let gid = attributes.gid.unwrap_or_else(|| metadata.gid());
+        let mut lines = vec![
+            (heading, EntryStyle::Yellow),
+            (
+                String::from("          read   write  exec"),
+                EntryStyle::Gray,
+            ),
+            (format!("user      {}", bits(6)), EntryStyle::Blue),
+            (format!("group     {}", bits(3)), EntryStyle::Blue),
+            (format!("other     {}", bits(0)), EntryStyle::Blue),
+            (
+                String::from("          setuid setgid sticky"),
+                EntryStyle::Gray,
+            ),
+            (format!("special   {}", bits(9)), EntryStyle::Blue),
+            (String::new(), EntryStyle::Gray),
+            (format!("mode      {:04o}", mode), EntryStyle::Cyan),
+            (
+                format!("owner     {}:{}", user_name(uid), group_name(gid)),
+                EntryStyle::Cyan,
+            ),
+        ];
+        if has_dirs {
+            let line = format!("{} recursive (R)", check(recursive));
+            lines.push((line, EntryStyle::Cyan));
+        }
+        state.popup = Some(Popup {
+            title: String::from(" chmod: r/w/x toggle, o octal, c owner, enter apply, q cancel "),
+            lines,
+            selected: Some([2, 3, 4, 6][row]),
+        });
+        ui.refresh(state)?;
+        let key = ui.get_next_keypress();
+        if move_selection(key, &mut row, 4) {
+            continue;
+        }
+        match key {
+            Key::Char(c @ ('r' | 'w' | 'x')) => {
+                let shift = if row == 3 { 9 } else { 3 * (2 - row as u32) };
+                let bit = match c {
+                    'r' => 2,
+                    'w' => 1,
+                    _ => 0,
+                };
+                mode ^= 1 << (shift + bit);
+            }
+            Key::Char('o') => {
+                let input = ui.get_user_input(state, "Octal mode: ")?;
+                match u32::from_str_radix(&input, 8) {
+                    Ok(octal) if octal <= 0o7777 => {
+                        mode = octal;
+                        octal_mode = true;
+                    }
+                    _ if input.is_empty() => {}
+                    _ => state.message = Some(format!("invalid mode {}", input)),
+                }
+            }
+            Key::Char('c') => {
+                // user, user:group or :group
+                let input = ui.get_user_input(state, "Owner[:group]: ")?;
+                let (user, group) = input.split_once(':').unwrap_or((&input, ""));
+                if !user.is_empty() {
+                    match user_id(user) {
+                        Some(uid) => attributes.uid = Some(uid),
+                        None => state.message = Some(format!("unknown user {}", user)),
+                    }
+                }
+                if !group.is_empty() {
+                    match group_id(group) {
+                        Some(gid) => attributes.gid = Some(gid),
+                        None => state.message = Some(format!("unknown group {}", group)),
+                    }
+                }
+            }
+            Key::Char('R') if has_dirs => recursive = !recursive,
+            Key::Char('\n') => {
+                attributes.mode = mode;
+                attributes.changed = if octal_mode { 0o7777 } else { mode ^ initial };
+                if attributes.changed != 0 || attributes.uid.is_some() || attributes.gid.is_some() {
+                    state.set_attributes(targets, attributes, recursive);
+                }
+                break;
+            }
+            Key::Char('q') | Key::Esc => break,
+            _ => {}
+        }
+    }
+    state.popup = None;
+    Ok(())
+}
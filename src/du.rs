@@ -0,0 +1,197 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Condvar, Mutex, OnceLock,
+    },
+    thread,
+};
+
+// Recursive directory sizes, calculated on a worker thread and cached until
+// something changes the file system. The size of a directory is the sum of
+// the sizes of all files below it. Like du, files with several hardlinks are
+// counted once and other file systems mounted below a directory are left
+// out, unless crossing file systems got switched on
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Usage {
+    pub bytes: u64,
+    pub items: u64, // files and directories below the directory
+}
+
+#[derive(Default)]
+struct Cache {
+    usages: HashMap<PathBuf, Usage>,
+    queue: Vec<PathBuf>, // the last requested directory comes first
+    scanning: Option<PathBuf>,
+}
+
+struct Scanner {
+    cache: Mutex<Cache>,
+    wakeup: Condvar,
+    // bumped by invalidate, a scan of an older generation is thrown away
+    generation: AtomicU64,
+    cross_filesystems: AtomicBool,
+    scanned: AtomicU64, // entries seen by the running scan
+}
+
+fn scanner() -> &'static Scanner {
+    static SCANNER: OnceLock<Scanner> = OnceLock::new();
+    SCANNER.get_or_init(|| {
+        thread::spawn(work);
+        Scanner {
+            cache: Mutex::new(Cache::default()),
+            wakeup: Condvar::new(),
+            generation: AtomicU64::new(0),
+            cross_filesystems: AtomicBool::new(false),
+            scanned: AtomicU64::new(0),
+        }
+    })
+}
+
+// the cached usage of a directory, None if it wasn't calculated (yet)
+pub fn usage(dir: &Path) -> Option<Usage> {
+    scanner().cache.lock().ok()?.usages.get(dir).copied()
+}
+
+// queues the calculation of the directory's usage unless it is known
+pub fn request(dir: &Path) {
+    let scanner = scanner();
+    if let Ok(mut cache) = scanner.cache.lock() {
+        if cache.usages.contains_key(dir) || cache.scanning.as_deref() == Some(dir) {
+            return;
+        }
+        cache.queue.retain(|queued| queued != dir);
+        cache.queue.push(dir.to_path_buf());
+        scanner.wakeup.notify_one();
+    }
+}
+
+// whether calculations are queued or running
+pub fn is_scanning() -> bool {
+    scanner()
+        .cache
+        .lock()
+        .is_ok_and(|cache| cache.scanning.is_some() || !cache.queue.is_empty())
+}
+
+// the number of entries the running scan has seen so far
+pub fn progress() -> u64 {
+    scanner().scanned.load(Ordering::Relaxed)
+}
+
+// drops a deleted path from the cache, the directories above it shrink by
+// its usage (the path itself counts as an item)
+pub fn forget(pathb: &Path, removed: Usage) {
+    if let Ok(mut cache) = scanner().cache.lock() {
+        cache.usages.retain(|dir, _| !dir.starts_with(pathb));
+        for ancestor in pathb.ancestors().skip(1) {
+            if let Some(usage) = cache.usages.get_mut(ancestor) {
+                usage.bytes = usage.bytes.saturating_sub(removed.bytes);
+                usage.items = usage.items.saturating_sub(removed.items + 1);
+            }
+        }
+    }
+}
+
+// forgets all usages, called whenever files were changed
+pub fn invalidate() {
+    let scanner = scanner();
+    scanner.generation.fetch_add(1, Ordering::Relaxed);
+    if let Ok(mut cache) = scanner.cache.lock() {
+        cache.usages.clear();
+        cache.queue.clear();
+    }
+}
+
+pub fn crosses_filesystems() -> bool {
+    scanner().cross_filesystems.load(Ordering::Relaxed)
+}
+
+pub fn set_cross_filesystems(cross: bool) {
+    scanner().cross_filesystems.store(cross, Ordering::Relaxed);
+    invalidate();
+}
+
+// the worker thread, scans one requested directory after the other
+fn work() {
+    let scanner = scanner();
+    loop {
+        let dir = {
+            let mut cache = match scanner.cache.lock() {
+                Ok(cache) => cache,
+                Err(_) => return,
+            };
+            cache.scanning = None;
+            while cache.queue.is_empty() {
+                cache = match scanner.wakeup.wait(cache) {
+                    Ok(cache) => cache,
+                    Err(_) => return,
+                };
+            }
+            let dir = cache.queue.pop().unwrap_or_default();
+            cache.scanning = Some(dir.clone());
+            dir
+        };
+        let generation = scanner.generation.load(Ordering::Relaxed);
+        scanner.scanned.store(0, Ordering::Relaxed);
+        let mut scan = Scan {
+            generation,
+            cross_filesystems: scanner.cross_filesystems.load(Ordering::Relaxed),
+            seen: HashSet::new(),
+            usages: HashMap::new(),
+        };
+        let device = fs::metadata(&dir).map(|metadata| metadata.dev());
+        if let Ok(device) = device {
+            if scan.dir(&dir, device).is_some() {
+                if let Ok(mut cache) = scanner.cache.lock() {
+                    if scanner.generation.load(Ordering::Relaxed) == generation {
+                        cache.usages.extend(scan.usages);
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct Scan {
+    generation: u64,
+    cross_filesystems: bool,
+    seen: HashSet<(u64, u64)>, // (device, inode) of files with hardlinks
+    // the usage of every directory scanned on the way, they are cached too
+    usages: HashMap<PathBuf, Usage>,
+}
+
+impl Scan {
+    // the usage of dir, None if the scan got outdated
+    fn dir(&mut self, dir: &Path, device: u64) -> Option<Usage> {
+        if scanner().generation.load(Ordering::Relaxed) != self.generation {
+            return None;
+        }
+        let mut usage = Usage::default();
+        // unreadable directories count as empty
+        for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+            if metadata.dev() != device && !self.cross_filesystems {
+                continue;
+            }
+            usage.items += 1;
+            scanner().scanned.fetch_add(1, Ordering::Relaxed);
+            if metadata.is_dir() {
+                let below = self.dir(&entry.path(), metadata.dev())?;
+                usage.bytes += below.bytes;
+                usage.items += below.items;
+            } else if metadata.nlink() < 2 || self.seen.insert((metadata.dev(), metadata.ino())) {
+                usage.bytes += metadata.len();
+            }
+        }
+        self.usages.insert(dir.to_path_buf(), usage);
+        Some(usage)
+    }
+}
@@ -0,0 +1,135 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{Error, Read},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::jobs::JobContext;
+
+// Finds identical files below some directories. Only files of the same size
+// can be identical, those are compared by a hash of their start first and,
+// if that matches as well, by a hash of their whole content. Paths that are
+// hardlinks to the same file are not duplicates of each other, only one of
+// them is looked at
+
+// the start of a file that is hashed before the whole file
+const PARTIAL: u64 = 64 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct Group {
+    pub size: u64,
+    pub paths: Vec<PathBuf>, // sorted, at least two
+}
+
+// the groups of identical files below roots, largest files first. Empty
+// files and symlinks are ignored
+pub fn find(roots: &[PathBuf], ctx: &JobContext) -> Result<Vec<Group>, Error> {
+    let mut by_size: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    let mut inodes = HashSet::new();
+    for root in roots {
+        collect(root, &mut by_size, &mut inodes, ctx)?;
+    }
+    let candidates = by_size
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .collect::<Vec<(u64, Vec<PathBuf>)>>();
+    for (size, paths) in candidates.iter() {
+        ctx.add_total(
+            (*size).min(PARTIAL) * paths.len() as u64,
+            paths.len() as u64,
+        );
+    }
+    let mut groups = Vec::new();
+    for (size, paths) in candidates {
+        for partial in split_by_hash(&paths, Some(PARTIAL), ctx)? {
+            let identical = if size <= PARTIAL {
+                vec![partial]
+            } else {
+                ctx.add_total(size * partial.len() as u64, 0);
+                split_by_hash(&partial, None, ctx)?
+            };
+            for mut paths in identical {
+                paths.sort();
+                groups.push(Group { size, paths });
+            }
+        }
+    }
+    groups.sort_by(|x, y| y.size.cmp(&x.size).then_with(|| x.paths.cmp(&y.paths)));
+    Ok(groups)
+}
+
+// puts every regular file below dir into by_size
+fn collect(
+    dir: &Path,
+    by_size: &mut HashMap<u64, Vec<PathBuf>>,
+    inodes: &mut HashSet<(u64, u64)>,
+    ctx: &JobContext,
+) -> Result<(), Error> {
+    ctx.checkpoint()?;
+    // unreadable directories are skipped
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        if metadata.is_dir() {
+            collect(&entry.path(), by_size, inodes, ctx)?;
+        } else if metadata.is_file()
+            && metadata.len() > 0
+            && inodes.insert((metadata.dev(), metadata.ino()))
+        {
+            by_size
+                .entry(metadata.len())
+                .or_default()
+                .push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+// groups the paths by the hash of their first limit bytes (or their whole
+// content), groups of a single path are dropped. Unreadable files are too
+fn split_by_hash(
+    paths: &[PathBuf],
+    limit: Option<u64>,
+    ctx: &JobContext,
+) -> Result<Vec<Vec<PathBuf>>, Error> {
+    let mut by_hash: HashMap<Vec<u8>, Vec<PathBuf>> = HashMap::new();
+    for pathb in paths {
+        ctx.checkpoint()?;
+        if let Ok(hash) = hash(pathb, limit, ctx) {
+            by_hash.entry(hash).or_default().push(pathb.clone());
+        }
+        if limit.is_some() {
+            ctx.file_done();
+        }
+    }
+    Ok(by_hash
+        .into_values()
+        .filter(|paths| paths.len() > 1)
+        .collect())
+}
+
+fn hash(pathb: &Path, limit: Option<u64>, ctx: &JobContext) -> Result<Vec<u8>, Error> {
+    let file = File::open(pathb)?;
+    let mut reader: Box<dyn Read> = match limit {
+        Some(limit) => Box::new(file.take(limit)),
+        None => Box::new(file),
+    };
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        ctx.advance(read as u64);
+        ctx.checkpoint()?;
+    }
+    Ok(hasher.finalize().to_vec())
+}
@@ -0,0 +1,775 @@
+use std::{
+    fs,
+    io::{Error, ErrorKind, Read, Write},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+use filetime::FileTime;
+
+use crate::{archive, jobs::JobContext, journal::Operation, trash, vfs};
+
+// Built-in file operations (copy, move, delete, rename, mkdir). They replace
+// shelling out to cp, mv and co. once per marked file, work with any
+// characters in paths and report failures for every single file. The ones
+// that may take long report their progress to a JobContext
+
+#[derive(Debug, Default)]
+pub struct OpReport {
+    pub done: Vec<PathBuf>, // the resulting paths of successful operations
+    pub failed: Vec<(PathBuf, Error)>, // every file that failed with the reason
+    pub operations: Vec<Operation>, // what was changed, for the journal
+}
+
+impl OpReport {
+    // a one line summary for the status bar, e.g. "copied 3, 1 failed: ..."
+    pub fn summary(&self, verb: &str) -> String {
+        let mut summary = format!("{} {}", verb, self.done.len());
+        if let Some((pathb, error)) = self.failed.first() {
+            summary += &format!(
+                ", {} failed: {}: {}",
+                self.failed.len(),
+                pathb.display(),
+                error
+            );
+        }
+        summary
+    }
+
+    fn fail(&mut self, pathb: &Path, error: Error) {
+        self.failed.push((pathb.to_path_buf(), error));
+    }
+}
+
+// a copy or move of source to dest. If replace is set, an existing dest
+// is moved into the trash first, so that overwriting can be undone
+#[derive(Debug, Clone)]
+pub struct Transfer {
+    pub source: PathBuf,
+    pub dest: PathBuf,
+    pub replace: bool,
+}
+
+impl Transfer {
+    // source into dest_dir under its own name
+    pub fn into_dir(source: &Path, dest_dir: &Path) -> Self {
+        Transfer {
+            source: source.to_path_buf(),
+            dest: dest_dir.join(source.file_name().unwrap_or_default()),
+            replace: false,
+        }
+    }
+}
+
+// copies all sources to their destinations, directories recursively
+pub fn copy_transfers(transfers: &[Transfer], ctx: &JobContext) -> OpReport {
+    let mut report = OpReport::default();
+    for transfer in transfers {
+        let (bytes, files) = tree_size(&transfer.source);
+        ctx.add_total(bytes, files);
+    }
+    for transfer in transfers {
+        let Transfer { source, dest, .. } = transfer;
+        if let Err(error) = ctx.checkpoint() {
+            report.fail(source, error);
+            break;
+        }
+        match check_into_itself(transfer)
+            .and_then(|()| clear_destination(transfer, &mut report, ctx))
+        {
+            Ok(()) => {
+                let failed_before = report.failed.len();
+                copy_path(source, dest, &mut report, ctx);
+                if report.failed.len() == failed_before {
+                    report.operations.push(Operation::Copy {
+                        from: source.clone(),
+                        to: dest.clone(),
+                    });
+                    report.done.push(dest.clone());
+                }
+            }
+            Err(error) => report.fail(source, error),
+        }
+    }
+    report
+}
+
+// moves all sources to their destinations, falls back to copying and
+// removing when the destination is on another file system
+pub fn move_transfers(transfers: &[Transfer], ctx: &JobContext) -> OpReport {
+    let mut report = OpReport::default();
+    ctx.add_total(0, transfers.len() as u64);
+    for transfer in transfers {
+        let Transfer { source, dest, .. } = transfer;
+        if let Err(error) = ctx.checkpoint() {
+            report.fail(source, error);
+            break;
+        }
+        if let Err(error) = check_writable(source)
+            .and_then(|()| check_into_itself(transfer))
+            .and_then(|()| clear_destination(transfer, &mut report, ctx))
+        {
+            report.fail(source, error);
+            continue;
+        }
+        let operation = Operation::Move {
+            from: source.clone(),
+            to: dest.clone(),
+        };
+        match fs::rename(source, dest) {
+            Ok(()) => {
+                report.operations.push(operation);
+                report.done.push(dest.clone());
+            }
+            Err(error) if error.raw_os_error() == Some(libc::EXDEV) => {
+                let (bytes, files) = tree_size(source);
+                ctx.add_total(bytes, files);
+                let failed_before = report.failed.len();
+                copy_path(source, dest, &mut report, ctx);
+                if report.failed.len() == failed_before {
+                    match remove_path(source) {
+                        Ok(()) => {
+                            report.operations.push(operation);
+                            report.done.push(dest.clone());
+                        }
+                        Err(error) => report.fail(source, error),
+                    }
+                } else if dest.symlink_metadata().is_ok() {
+                    // the source is still complete, the partial copy of it
+                    // (dest was free before) would only be in the way
+                    if let Err(error) = remove_path(dest) {
+                        report.fail(dest, error);
+                    }
+                }
+            }
+            Err(error) => report.fail(source, error),
+        }
+        ctx.file_done();
+    }
+    report
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LinkKind {
+    Absolute, // a symlink to the absolute path
+    Relative, // a symlink to the path relative to the link's directory
+    Hard,
+}
+
+// creates a link at every destination pointing to its source
+pub fn link_transfers(transfers: &[Transfer], kind: LinkKind, ctx: &JobContext) -> OpReport {
+    let mut report = OpReport::default();
+    ctx.add_total(0, transfers.len() as u64);
+    for transfer in transfers {
+        let Transfer { source, dest, .. } = transfer;
+        if let Err(error) = ctx.checkpoint() {
+            report.fail(source, error);
+            break;
+        }
+        let dest_dir = dest.parent().unwrap_or_else(|| Path::new("/"));
+        let target = match kind {
+            LinkKind::Relative => relative_path(source, dest_dir),
+            LinkKind::Absolute | LinkKind::Hard => source.clone(),
+        };
+        let hard = kind == LinkKind::Hard;
+        let result = clear_destination(transfer, &mut report, ctx)
+            .and_then(|()| create_link(&target, dest, hard));
+        match result {
+            Ok(()) => {
+                report.operations.push(Operation::Link {
+                    target,
+                    path: dest.clone(),
+                    hard,
+                });
+                report.done.push(dest.clone());
+            }
+            Err(error) => report.fail(source, error),
+        }
+        ctx.file_done();
+    }
+    report
+}
+
+pub fn create_link(target: &Path, path: &Path, hard: bool) -> Result<(), Error> {
+    if hard {
+        fs::hard_link(target, path)
+    } else {
+        std::os::unix::fs::symlink(target, path)
+    }
+}
+
+// the path that leads from the directory dir to target, both absolute
+fn relative_path(target: &Path, dir: &Path) -> PathBuf {
+    let common = target
+        .components()
+        .zip(dir.components())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let mut relative = PathBuf::new();
+    for _ in dir.components().skip(common) {
+        relative.push("..");
+    }
+    relative.extend(target.components().skip(common));
+    relative
+}
+
+// deletes all given paths permanently, directories recursively
+pub fn delete(paths: &[PathBuf], ctx: &JobContext) -> OpReport {
+    let mut report = OpReport::default();
+    ctx.add_total(0, paths.len() as u64);
+    for pathb in paths {
+        if let Err(error) = ctx.checkpoint() {
+            report.fail(pathb, error);
+            break;
+        }
+        match check_writable(pathb).and_then(|()| remove_path(pathb)) {
+            Ok(()) => report.done.push(pathb.clone()),
+            Err(error) => report.fail(pathb, error),
+        }
+        ctx.file_done();
+    }
+    report
+}
+
+// the changes of the permission editor. Only the mode bits in changed are
+// set (to their value in mode), None leaves the owner as it is
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Attributes {
+    pub mode: u32,
+    pub changed: u32,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+impl Attributes {
+    // the new mode of an entry. Like chmod's X, files inside of a directory
+    // only get exec bits if someone could already execute them
+    fn apply(&self, old: u32, is_dir: bool, nested: bool) -> u32 {
+        let mut changed = self.changed;
+        if nested && !is_dir && old & 0o111 == 0 {
+            changed &= !(self.mode & 0o111);
+        }
+        (old & 0o7777 & !changed) | (self.mode & changed)
+    }
+}
+
+// changes the mode and owner of all paths, with recursive of everything
+// inside of directories too. The mode of symlinks is left alone, chmod
+// would follow them
+pub fn set_attributes(
+    paths: &[PathBuf],
+    attributes: Attributes,
+    recursive: bool,
+    ctx: &JobContext,
+) -> OpReport {
+    let mut report = OpReport::default();
+    for pathb in paths {
+        let files = if recursive { tree_size(pathb).1 } else { 1 };
+        ctx.add_total(0, files);
+    }
+    for pathb in paths {
+        if let Err(error) = ctx.checkpoint() {
+            report.fail(pathb, error);
+            break;
+        }
+        let failed_before = report.failed.len();
+        set_path_attributes(pathb, attributes, recursive, false, &mut report, ctx);
+        if report.failed.len() == failed_before {
+            report.done.push(pathb.clone());
+        }
+    }
+    report
+}
+
+fn set_path_attributes(
+    pathb: &Path,
+    attributes: Attributes,
+    recursive: bool,
+    nested: bool,
+    report: &mut OpReport,
+    ctx: &JobContext,
+) {
+    let metadata = match pathb.symlink_metadata() {
+        Ok(metadata) => metadata,
+        Err(error) => return report.fail(pathb, error),
+    };
+    let old = metadata.permissions().mode();
+    let mode = attributes.apply(old, metadata.is_dir(), nested);
+    let change_mode = attributes.changed != 0 && !metadata.file_type().is_symlink();
+    // a directory is changed after its content, removing r or x from it
+    // first would lock us out. Unless it's the other way round
+    let unlocks = (mode & !old) & 0o500 != 0;
+    let recurse = recursive && metadata.is_dir();
+    let mut result = Ok(());
+    if recurse && !unlocks {
+        set_dir_content_attributes(pathb, attributes, report, ctx);
+    }
+    // chown comes first, it clears the setuid and setgid bits
+    if attributes.uid.is_some() || attributes.gid.is_some() {
+        result = std::os::unix::fs::lchown(pathb, attributes.uid, attributes.gid);
+    }
+    if change_mode {
+        result = result.and_then(|()| fs::set_permissions(pathb, fs::Permissions::from_mode(mode)));
+    }
+    if let Err(error) = result {
+        report.fail(pathb, error);
+    }
+    ctx.file_done();
+    if recurse && unlocks {
+        set_dir_content_attributes(pathb, attributes, report, ctx);
+    }
+}
+
+fn set_dir_content_attributes(
+    dir: &Path,
+    attributes: Attributes,
+    report: &mut OpReport,
+    ctx: &JobContext,
+) {
+    match fs::read_dir(dir) {
+        Ok(entries) => {
+            for entry in entries.flatten() {
+                if ctx.checkpoint().is_err() {
+                    return;
+                }
+                set_path_attributes(&entry.path(), attributes, true, true, report, ctx);
+            }
+        }
+        Err(error) => report.fail(dir, error),
+    }
+}
+
+// moves a single path to dest, across file systems if needed
+pub fn move_path(source: &Path, dest: &Path, ctx: &JobContext) -> Result<(), Error> {
+    match fs::rename(source, dest) {
+        Err(error) if error.raw_os_error() == Some(libc::EXDEV) => {
+            copy_tree(source, dest, ctx)?;
+            remove_path(source)
+        }
+        result => result,
+    }
+}
+
+// copies a single path to dest, fails with the first error
+pub fn copy_tree(source: &Path, dest: &Path, ctx: &JobContext) -> Result<(), Error> {
+    let mut report = OpReport::default();
+    copy_path(source, dest, &mut report, ctx);
+    match report.failed.into_iter().next() {
+        Some((_, error)) => Err(error),
+        None => Ok(()),
+    }
+}
+
+// moves every pair (from, to) at once, a destination may be the source of
+// another pair (swaps and cycles). All sources are moved to temporary names
+// next to them first, then to their destinations. If a step fails, the
+// steps done so far are rolled back
+pub fn move_all(pairs: &[(PathBuf, PathBuf)]) -> Result<(), Error> {
+    let pid = std::process::id();
+    let mut temps = Vec::new();
+    for (idx, (from, _)) in pairs.iter().enumerate() {
+        let name = from.file_name().unwrap_or_default().to_string_lossy();
+        let temp = from.with_file_name(format!(".{}.mampf-{}-{}", name, pid, idx));
+        if let Err(error) = fs::rename(from, &temp) {
+            for (temp, (from, _)) in temps.iter().zip(pairs) {
+                let _ = fs::rename(temp, from);
+            }
+            return Err(error);
+        }
+        temps.push(temp);
+    }
+    for (idx, (temp, (_, to))) in temps.iter().zip(pairs).enumerate() {
+        if to.symlink_metadata().is_ok() {
+            let error = Error::new(ErrorKind::AlreadyExists, format!("{} exists", to.display()));
+            rollback_move_all(pairs, &temps, idx);
+            return Err(error);
+        }
+        if let Err(error) = move_path(temp, to, &JobContext::default()) {
+            rollback_move_all(pairs, &temps, idx);
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
+// undoes the first done steps of the second phase of move_all and moves
+// every temporary name back to its source
+fn rollback_move_all(pairs: &[(PathBuf, PathBuf)], temps: &[PathBuf], done: usize) {
+    for (temp, (_, to)) in temps.iter().zip(pairs).take(done) {
+        let _ = fs::rename(to, temp);
+    }
+    for (temp, (from, _)) in temps.iter().zip(pairs) {
+        let _ = fs::rename(temp, from);
+    }
+}
+
+// renames the path to new_name inside of the same directory
+pub fn rename(pathb: &Path, new_name: &str) -> Result<PathBuf, Error> {
+    check_writable(pathb)?;
+    let parent = pathb
+        .parent()
+        .ok_or_else(|| invalid("can't rename the root"))?;
+    let dest = parent.join(valid_name(new_name)?);
+    if dest.symlink_metadata().is_ok() {
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            "destination already exists",
+        ));
+    }
+    fs::rename(pathb, &dest)?;
+    Ok(dest)
+}
+
+// creates the directory rel (like a/b/c) inside of dir with all missing
+// parents, like mkdir -p. Returns the created directories, outermost first
+pub fn mkdir(dir: &Path, rel: &str) -> Result<Vec<PathBuf>, Error> {
+    let dest = nested_path(dir, rel)?;
+    let mut created = create_parents(dir, &dest)?;
+    match fs::create_dir(&dest) {
+        Ok(()) => {
+            created.push(dest);
+            Ok(created)
+        }
+        Err(error) => {
+            remove_created(&created);
+            Err(error)
+        }
+    }
+}
+
+// creates the file rel (like a/b/c.sh) inside of dir with all missing
+// parents. Returns the created directories, outermost first, and the file
+pub fn touch(
+    dir: &Path,
+    rel: &str,
+    template: Option<&Path>,
+) -> Result<(Vec<PathBuf>, PathBuf), Error> {
+    let dest = nested_path(dir, rel)?;
+    let created = create_parents(dir, &dest)?;
+    match create_file(&dest, template) {
+        Ok(()) => Ok((created, dest)),
+        Err(error) => {
+            remove_created(&created);
+            Err(error)
+        }
+    }
+}
+
+// creates a new file, it starts as a copy of the template (content and
+// permissions, e.g. the exec bit of a script skeleton) if one is given
+pub fn create_file(dest: &Path, template: Option<&Path>) -> Result<(), Error> {
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(dest)?;
+    if let Some(template) = template {
+        let result = fs::File::open(template)
+            .and_then(|mut content| std::io::copy(&mut content, &mut file))
+            .and_then(|_| template.metadata())
+            .and_then(|metadata| fs::set_permissions(dest, metadata.permissions()));
+        if let Err(error) = result {
+            let _ = fs::remove_file(dest);
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
+// the path typed by the user inside of dir, every component has to be a
+// valid name (a/b/c is fine, /a or a/../b aren't)
+fn nested_path(dir: &Path, rel: &str) -> Result<PathBuf, Error> {
+    if rel.starts_with('/') {
+        return Err(invalid("the path has to be relative"));
+    }
+    let mut dest = dir.to_path_buf();
+    for component in rel.split('/').filter(|component| !component.is_empty()) {
+        dest.push(valid_name(component)?);
+    }
+    if dest == dir {
+        return Err(invalid("invalid file name"));
+    }
+    Ok(dest)
+}
+
+// creates the missing directories between dir and dest, outermost first
+fn create_parents(dir: &Path, dest: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut created = Vec::new();
+    let mut parents = dest
+        .ancestors()
+        .skip(1)
+        .take_while(|parent| parent != &dir)
+        .collect::<Vec<_>>();
+    parents.reverse();
+    for parent in parents {
+        let result = match parent.symlink_metadata() {
+            Ok(_) if parent.is_dir() => continue,
+            Ok(_) => Err(invalid(&format!("{} is not a directory", parent.display()))),
+            Err(_) => fs::create_dir(parent),
+        };
+        match result {
+            Ok(()) => created.push(parent.to_path_buf()),
+            Err(error) => {
+                remove_created(&created);
+                return Err(error);
+            }
+        }
+    }
+    Ok(created)
+}
+
+fn remove_created(created: &[PathBuf]) {
+    for dir in created.iter().rev() {
+        let _ = fs::remove_dir(dir);
+    }
+}
+
+// files inside of archives can only be copied
+pub fn check_writable(pathb: &Path) -> Result<(), Error> {
+    if vfs::is_virtual(pathb) {
+        Err(invalid("archives are read-only"))
+    } else {
+        Ok(())
+    }
+}
+
+fn check_into_itself(transfer: &Transfer) -> Result<(), Error> {
+    let dest_dir = transfer.dest.parent().unwrap_or_else(|| Path::new("/"));
+    if dest_dir.starts_with(&transfer.source) {
+        Err(invalid("can't copy or move a directory into itself"))
+    } else {
+        Ok(())
+    }
+}
+
+// makes room for a transfer, an existing destination is only trashed if
+// the transfer replaces it
+fn clear_destination(
+    transfer: &Transfer,
+    report: &mut OpReport,
+    ctx: &JobContext,
+) -> Result<(), Error> {
+    let Transfer {
+        source,
+        dest,
+        replace,
+    } = transfer;
+    if dest.symlink_metadata().is_err() {
+        return Ok(());
+    }
+    if !replace || dest == source {
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            "destination already exists",
+        ));
+    }
+    let item = trash::trash_path(dest, ctx)?;
+    report.operations.push(Operation::Trash {
+        from: item.original,
+        to: item.file,
+        info: item.info,
+    });
+    Ok(())
+}
+
+// the first of "name (2).ext", "name (3).ext", ... that isn't taken
+pub fn free_name(dest: &Path, taken: impl Fn(&Path) -> bool) -> PathBuf {
+    // foo.tar.gz is numbered as foo (2).tar.gz, not foo.tar (2).gz
+    let (stem, extension) = match archive::stem(dest) {
+        Some(stem) => {
+            let name = dest.file_name().unwrap_or_default().to_string_lossy();
+            let extension = name[stem.len() + 1..].to_string();
+            (stem.into(), Some(extension.into()))
+        }
+        None => (
+            dest.file_stem().unwrap_or_default().to_os_string(),
+            dest.extension().map(|extension| extension.to_os_string()),
+        ),
+    };
+    (2..)
+        .map(|counter| {
+            let mut name = stem.clone();
+            name.push(format!(" ({})", counter));
+            if let Some(extension) = &extension {
+                name.push(".");
+                name.push(extension);
+            }
+            dest.with_file_name(name)
+        })
+        .find(|candidate| !taken(candidate))
+        .unwrap_or_else(|| dest.to_path_buf())
+}
+
+pub fn valid_name(name: &str) -> Result<&str, Error> {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+        Err(invalid("invalid file name"))
+    } else {
+        Ok(name)
+    }
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidInput, msg)
+}
+
+// the size in bytes and the number of files of a directory tree
+pub fn tree_size(pathb: &Path) -> (u64, u64) {
+    match pathb.symlink_metadata() {
+        Ok(metadata) if metadata.is_dir() => fs::read_dir(pathb)
+            .map(|entries| {
+                entries.flatten().fold((0, 0), |(bytes, files), entry| {
+                    let (sub_bytes, sub_files) = tree_size(&entry.path());
+                    (bytes + sub_bytes, files + sub_files)
+                })
+            })
+            .unwrap_or((0, 0)),
+        Ok(metadata) => (metadata.len(), 1),
+        Err(_) => vfs::tree_size(pathb),
+    }
+}
+
+// copies a file, symlink or directory tree preserving permissions and
+// timestamps, every failing file is added to the report
+fn copy_path(source: &Path, dest: &Path, report: &mut OpReport, ctx: &JobContext) {
+    if let Err(error) = ctx.checkpoint() {
+        return report.fail(source, error);
+    }
+    let metadata = match source.symlink_metadata() {
+        Ok(metadata) => metadata,
+        // copying out of an archive extracts
+        Err(error) => {
+            let result = match vfs::split(source) {
+                Some((archive, member)) => archive::extract(&archive, &member, dest, ctx),
+                None => Err(error),
+            };
+            if let Err(error) = result {
+                report.fail(source, error);
+            }
+            return;
+        }
+    };
+    let file_type = metadata.file_type();
+    let result = if file_type.is_symlink() {
+        fs::read_link(source).and_then(|target| std::os::unix::fs::symlink(target, dest))
+    } else if file_type.is_dir() {
+        if let Err(error) = fs::create_dir(dest) {
+            return report.fail(source, error);
+        }
+        match fs::read_dir(source) {
+            Ok(entries) => {
+                for entry in entries {
+                    match entry {
+                        Ok(entry) => {
+                            copy_path(&entry.path(), &dest.join(entry.file_name()), report, ctx)
+                        }
+                        Err(error) => report.fail(source, error),
+                    }
+                }
+            }
+            Err(error) => report.fail(source, error),
+        }
+        // permissions and times are set last, the copied content would
+        // otherwise change the mtime again
+        fs::set_permissions(dest, metadata.permissions())
+    } else if !file_type.is_file() {
+        // reading a fifo or a device would block forever or never end
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            "can't copy special files (fifos, sockets, devices)",
+        ))
+    } else {
+        copy_file(source, dest, ctx)
+            .and_then(|()| fs::set_permissions(dest, metadata.permissions()))
+    };
+    let result = result.and_then(|()| {
+        let atime = FileTime::from_last_access_time(&metadata);
+        let mtime = FileTime::from_last_modification_time(&metadata);
+        if file_type.is_symlink() {
+            filetime::set_symlink_file_times(dest, atime, mtime)
+        } else {
+            filetime::set_file_times(dest, atime, mtime)
+        }
+    });
+    if !file_type.is_dir() {
+        ctx.file_done();
+    }
+    if let Err(error) = result {
+        report.fail(source, error);
+    }
+}
+
+// copies the content of a single file in chunks, so that the progress can
+// be shown and the job can be paused or cancelled in between
+fn copy_file(source: &Path, dest: &Path, ctx: &JobContext) -> Result<(), Error> {
+    let mut reader = fs::File::open(source)?;
+    let mut writer = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(dest)?;
+    let mut buffer = vec![0; 1 << 16];
+    loop {
+        let result = ctx.checkpoint().and_then(|()| reader.read(&mut buffer));
+        let read = match result {
+            Ok(0) => return Ok(()),
+            Ok(read) => read,
+            Err(error) if error.kind() == ErrorKind::Interrupted && !ctx.is_cancelled() => continue,
+            Err(error) => {
+                // don't leave half copied files behind
+                drop(writer);
+                let _ = fs::remove_file(dest);
+                return Err(error);
+            }
+        };
+        if let Err(error) = writer.write_all(&buffer[..read]) {
+            drop(writer);
+            let _ = fs::remove_file(dest);
+            return Err(error);
+        }
+        ctx.advance(read as u64);
+    }
+}
+
+pub fn remove_path(pathb: &Path) -> Result<(), Error> {
+    if pathb.symlink_metadata()?.is_dir() {
+        fs::remove_dir_all(pathb)
+    } else {
+        fs::remove_file(pathb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn free(name: &str, taken: &[&str]) -> PathBuf {
+        free_name(&Path::new("/dir").join(name), |candidate| {
+            taken
+                .iter()
+                .any(|name| candidate == Path::new("/dir").join(name))
+        })
+    }
+
+    #[test]
+    fn free_name_numbers_before_the_extension() {
+        assert_eq!(free("notes", &[]), Path::new("/dir/notes (2)"));
+        assert_eq!(free("notes.txt", &[]), Path::new("/dir/notes (2).txt"));
+        assert_eq!(free(".bashrc", &[]), Path::new("/dir/.bashrc (2)"));
+        assert_eq!(
+            free("notes.txt", &["notes (2).txt"]),
+            Path::new("/dir/notes (3).txt")
+        );
+    }
+
+    #[test]
+    fn free_name_keeps_archive_extensions_together() {
+        assert_eq!(
+            free("backup.tar.gz", &[]),
+            Path::new("/dir/backup (2).tar.gz")
+        );
+        assert_eq!(free("backup.tgz", &[]), Path::new("/dir/backup (2).tgz"));
+        assert_eq!(
+            free("Backup.TAR.GZ", &["Backup (2).TAR.GZ"]),
+            Path::new("/dir/Backup (3).TAR.GZ")
+        );
+        assert_eq!(free("a.b.tar", &[]), Path::new("/dir/a.b (2).tar"));
+    }
+}
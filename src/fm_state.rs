@@ -1,6 +1,9 @@
 use std::path::{Path, PathBuf};
 
-use crate::util::{Filter, SortBy};
+use crate::{
+    util::{read_preview, Filter, SortBy},
+    vfs,
+};
 
 // FMState holds all relevant methods and fields to reproduce the state
 // of a file manager. (Essentially a singleton as long as tabbing isn't a thing)
@@ -15,32 +18,35 @@ pub struct FMState {
 }
 
 impl FMState {
-    pub fn new() -> Self {
-        let marked: Vec<PathBuf> = Vec::new();
-        let mut current_dir = PathBuf::new();
-        let start_dir = match std::env::var("HOME") {
-            Ok(val) => val,
-            Err(_e) => "/".to_string(),
-        };
-        current_dir.push(start_dir);
-        let sort_by = SortBy::LexioInc;
-
-        let focused = sort_by.sort(Self::list(&current_dir)).pop();
-        FMState {
-            current_dir,
-            focused: focused,
-            marked,
+    // creates the state in the given directory or, if a file is given,
+    // in its parent directory with the file focused
+    pub fn new(start: PathBuf) -> Self {
+        let mut fm_state = FMState {
+            current_dir: PathBuf::from("/"),
+            focused: None,
+            marked: Vec::new(),
             filters: vec![Filter::Dotfiles],
-            sort_by,
+            sort_by: SortBy::LexioInc,
             exit: false,
+        };
+        if start.exists() {
+            fm_state.jump_to(start);
+        } else {
+            fm_state.jump_to(PathBuf::from("/"));
+        }
+        // a focused file that would be filtered out (like a selected dotfile)
+        // has to stay visible
+        if fm_state.focused.is_some() && fm_state.get_idx().is_none() {
+            fm_state.filters.clear();
         }
+        fm_state
     }
 
     pub fn is_marked(&self, pathb: PathBuf) -> bool {
         self.marked.iter().any(|pathb_cmp| pathb_cmp == &pathb)
     }
 
-    pub fn mark(&mut self, pathb: &PathBuf) {
+    pub fn mark(&mut self, pathb: &Path) {
         if !self.is_marked(pathb.to_path_buf()) {
             self.marked.push(pathb.to_path_buf());
         }
@@ -66,6 +72,10 @@ impl FMState {
             .for_each(|pathb| self.mark(pathb));
     }
 
+    pub fn unmark(&mut self, pathb: &Path) {
+        self.marked.retain(|marked| marked != pathb);
+    }
+
     pub fn unmark_all(&mut self) {
         self.marked.clear();
     }
@@ -88,6 +98,41 @@ impl FMState {
         }
     }
 
+    // focuses the given path if it is part of the current listing
+    pub fn focus(&mut self, pathb: &Path) {
+        if self.list_current().iter().any(|direle| direle == pathb) {
+            self.focused = Some(pathb.to_path_buf());
+        }
+    }
+
+    // shows the directory of the given path with the path focused
+    pub fn reveal(&mut self, pathb: &Path) {
+        if let Some(parent) = pathb.parent() {
+            self.current_dir = parent.to_path_buf();
+            self.focused = Some(pathb.to_path_buf());
+        }
+    }
+
+    // keeps the focus valid after entries were removed from the current
+    // directory, the entry that took the place of the removed one is focused
+    pub fn refocus(&mut self) {
+        let current_list = self.list_current();
+        let idx = match &self.focused {
+            Some(focused) if current_list.contains(focused) => return,
+            Some(focused) => {
+                let mut with_focused = current_list.clone();
+                with_focused.push(focused.clone());
+                self.order(&mut with_focused)
+                    .iter()
+                    .position(|direle| direle == focused)
+                    .unwrap_or(0)
+            }
+            None => 0,
+        };
+        let idx = idx.min(current_list.len().saturating_sub(1));
+        self.focused = current_list.get(idx).cloned();
+    }
+
     // moves out of the current dir, returns index of the former parent dir
     pub fn move_out(&mut self) {
         if let Some(dir) = self.current_dir.parent() {
@@ -97,12 +142,13 @@ impl FMState {
         self.update_by_idx(self.get_idx());
     }
 
-    // moves into the current focused dir if possible, returns new focused index
+    // moves into the current focused dir (or archive) if possible, returns
+    // new focused index
     pub fn move_in(&mut self) -> Option<()> {
-        if self.focused.as_ref()?.is_dir() {
+        if vfs::is_dir(self.focused.as_ref()?) {
             self.current_dir = self.focused.as_ref()?.clone();
             let current_list = self.list_current();
-            self.focused = Some(current_list.get(0)?.to_path_buf());
+            self.focused = Some(current_list.first()?.to_path_buf());
             self.update_by_idx(Some(0));
         }
         None
@@ -144,7 +190,7 @@ impl FMState {
         None
     }
 
-    fn order(&self, list: &mut Vec<PathBuf>) -> Vec<PathBuf> {
+    fn order(&self, list: &mut [PathBuf]) -> Vec<PathBuf> {
         // sort according to the sort_by property
         let mut list = self.sort_by.sort(list.to_vec());
         // remove filter if needed
@@ -178,8 +224,8 @@ impl FMState {
 
     pub fn list_next(&self) -> Vec<PathBuf> {
         if let Some(focused) = &self.focused {
-            if focused.is_dir() {
-                let mut a = Self::list(&focused);
+            if vfs::is_dir_cached(focused) {
+                let mut a = Self::list(focused);
                 self.order(&mut a)
             } else {
                 Vec::new()
@@ -190,16 +236,16 @@ impl FMState {
     }
 
     pub fn get_preview(&self) -> Option<String> {
-        std::fs::read_to_string(self.focused.as_ref()?).ok()
+        read_preview(self.focused.as_ref()?)
     }
 
-    pub fn list_previous(path: &PathBuf, depth: u8) -> Vec<PathBuf> {
-        if path.exists() && path.is_dir() {
+    pub fn list_previous(path: &Path, depth: u8) -> Vec<PathBuf> {
+        if vfs::is_dir(path) {
             if depth == 0 {
                 Self::list(path)
             } else {
                 match path.parent() {
-                    Some(parent_path) => Self::list_previous(&parent_path.to_path_buf(), depth - 1),
+                    Some(parent_path) => Self::list_previous(parent_path, depth - 1),
                     None => Vec::new(),
                 }
             }
@@ -208,20 +254,9 @@ impl FMState {
         }
     }
 
-    pub fn list(directory_path: &PathBuf) -> Vec<PathBuf> {
-        let mut list: Vec<PathBuf> = Vec::new();
-        let path = Path::new(&directory_path);
-        if let Ok(contents) = path.read_dir() {
-            for entry in contents {
-                match entry {
-                    Ok(ele) => list.push(ele.path()),
-                    Err(_error) => {}
-                }
-            }
-            list
-        } else {
-            Vec::new()
-        }
+    // lists real directories as well as archives and directories in them
+    pub fn list(directory_path: &Path) -> Vec<PathBuf> {
+        vfs::list(directory_path)
     }
 
     // a couple setter, getter fields to keep all fields private
@@ -239,8 +274,16 @@ impl FMState {
     }
 
     pub fn jump_to(&mut self, new_focused: PathBuf) -> Option<usize> {
-        if new_focused.is_dir() {
+        // archives are focused like files, only directories inside of them
+        // are entered
+        let is_dir = if new_focused.exists() {
+            new_focused.is_dir()
+        } else {
+            vfs::is_dir(&new_focused)
+        };
+        if is_dir {
             self.current_dir = new_focused;
+            self.focused = self.list_current().first().cloned();
             Some(0)
         } else {
             self.focused = Some(new_focused.clone());
@@ -261,5 +304,14 @@ impl FMState {
         self.marked.clone()
     }
 
+    // the marked entries or, if nothing is marked, the focused entry
+    pub fn get_selection(&self) -> Vec<PathBuf> {
+        if self.marked.is_empty() {
+            self.focused.iter().cloned().collect()
+        } else {
+            self.get_marked()
+        }
+    }
+
     // the following functions are to support executing shell commands
 }
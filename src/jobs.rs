@@ -0,0 +1,223 @@
+use std::{
+    io::{Error, ErrorKind},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{file_ops::OpReport, util::format_bytes};
+
+// Long running file operations are run as background jobs. Every job gets
+// its own thread and shares a JobContext with the main loop, through which
+// the worker reports its progress and the user pauses or cancels it
+
+#[derive(Debug, Default)]
+pub struct JobContext {
+    bytes_done: AtomicU64,
+    bytes_total: AtomicU64,
+    files_done: AtomicU64,
+    files_total: AtomicU64,
+    paused: AtomicBool,
+    cancelled: AtomicBool,
+}
+
+impl JobContext {
+    pub fn add_total(&self, bytes: u64, files: u64) {
+        self.bytes_total.fetch_add(bytes, Ordering::Relaxed);
+        self.files_total.fetch_add(files, Ordering::Relaxed);
+    }
+
+    pub fn advance(&self, bytes: u64) {
+        self.bytes_done.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn file_done(&self) {
+        self.files_done.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // called by workers between chunks of work: blocks while the job is
+    // paused and fails once the job got cancelled
+    pub fn checkpoint(&self) -> Result<(), Error> {
+        while self.paused.load(Ordering::Relaxed) && !self.is_cancelled() {
+            thread::sleep(Duration::from_millis(100));
+        }
+        if self.is_cancelled() {
+            Err(Error::new(ErrorKind::Interrupted, "cancelled"))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    // (bytes done, bytes total, files done, files total)
+    pub fn counts(&self) -> (u64, u64, u64, u64) {
+        (
+            self.bytes_done.load(Ordering::Relaxed),
+            self.bytes_total.load(Ordering::Relaxed),
+            self.files_done.load(Ordering::Relaxed),
+            self.files_total.load(Ordering::Relaxed),
+        )
+    }
+}
+
+pub struct Job {
+    pub id: usize,
+    pub title: String,
+    pub verb: String,          // used for the summary once the job is done
+    pub sources: Vec<PathBuf>, // the paths the job works on
+    context: Arc<JobContext>,
+    result: Arc<Mutex<Option<OpReport>>>,
+    started: Instant,
+}
+
+impl Job {
+    pub fn is_finished(&self) -> bool {
+        self.result.lock().map_or(true, |result| result.is_some())
+    }
+
+    pub fn pause(&self) {
+        self.context.paused.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume(&self) {
+        self.context.paused.store(false, Ordering::Relaxed);
+    }
+
+    pub fn cancel(&self) {
+        self.context.cancel();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.context.is_paused()
+    }
+
+    // the share of the work already done, by bytes if known, else by files
+    pub fn ratio(&self) -> f64 {
+        let (bytes_done, bytes_total, files_done, files_total) = self.counts();
+        let ratio = if bytes_total > 0 {
+            bytes_done as f64 / bytes_total as f64
+        } else if files_total > 0 {
+            files_done as f64 / files_total as f64
+        } else {
+            0.0
+        };
+        ratio.clamp(0.0, 1.0)
+    }
+
+    // e.g. "copy 3 entries 12MB/30MB 5/10 files ETA 0:12"
+    pub fn describe(&self) -> String {
+        let (bytes_done, bytes_total, files_done, files_total) = self.counts();
+        let mut text = format!(
+            "{} {}/{} {}/{} files",
+            self.title,
+            format_bytes(bytes_done),
+            format_bytes(bytes_total),
+            files_done,
+            files_total
+        );
+        if self.is_paused() {
+            text += " (paused)";
+        } else if let Some(eta) = self.eta() {
+            text += &format!(" ETA {}:{:02}", eta.as_secs() / 60, eta.as_secs() % 60);
+        }
+        text
+    }
+
+    fn eta(&self) -> Option<Duration> {
+        let ratio = self.ratio();
+        if ratio <= 0.0 || ratio >= 1.0 {
+            return None;
+        }
+        let elapsed = self.started.elapsed().as_secs_f64();
+        Some(Duration::from_secs_f64(elapsed / ratio - elapsed))
+    }
+
+    fn counts(&self) -> (u64, u64, u64, u64) {
+        self.context.counts()
+    }
+}
+
+#[derive(Default)]
+pub struct JobQueue {
+    pub jobs: Vec<Job>,
+    next_id: usize,
+}
+
+impl JobQueue {
+    // runs work on a new thread, the returned report is handed back by
+    // take_finished once the work is done. Returns the id of the job
+    pub fn spawn<F>(&mut self, title: String, verb: &str, sources: Vec<PathBuf>, work: F) -> usize
+    where
+        F: FnOnce(&JobContext) -> OpReport + Send + 'static,
+    {
+        let context = Arc::new(JobContext::default());
+        let result = Arc::new(Mutex::new(None));
+        let (thread_context, thread_result) = (context.clone(), result.clone());
+        thread::spawn(move || {
+            let report = work(&thread_context);
+            if let Ok(mut result) = thread_result.lock() {
+                *result = Some(report);
+            }
+        });
+        self.next_id += 1;
+        self.jobs.push(Job {
+            id: self.next_id,
+            title,
+            verb: verb.to_string(),
+            sources,
+            context,
+            result,
+            started: Instant::now(),
+        });
+        self.next_id
+    }
+
+    // removes all finished jobs and returns them with their reports
+    pub fn take_finished(&mut self) -> Vec<(Job, OpReport)> {
+        let mut finished = Vec::new();
+        let mut idx = 0;
+        while idx < self.jobs.len() {
+            if self.jobs[idx].is_finished() {
+                let job = self.jobs.remove(idx);
+                let report = job.result.lock().ok().and_then(|mut r| r.take());
+                finished.push((job, report.unwrap_or_default()));
+            } else {
+                idx += 1;
+            }
+        }
+        finished
+    }
+
+    pub fn has_unfinished(&self) -> bool {
+        self.jobs.iter().any(|job| !job.is_finished())
+    }
+
+    // finished jobs stay in the queue until the next take_finished
+    pub fn unfinished(&self) -> usize {
+        self.jobs.iter().filter(|job| !job.is_finished()).count()
+    }
+
+    pub fn cancel_all(&self) {
+        self.jobs.iter().for_each(|job| job.cancel());
+    }
+
+    // the job shown in the status bar, the oldest one still running
+    pub fn current(&self) -> Option<&Job> {
+        self.jobs.iter().find(|job| !job.is_finished())
+    }
+}
@@ -0,0 +1,547 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    archive, file_ops,
+    jobs::JobContext,
+    trash::{self, TrashItem},
+};
+
+// The journal remembers the file operations mampf performed, so that they
+// can be undone and redone. Before anything is touched, every path involved
+// is checked against the state it had right after the operation. If the
+// user (or anything else) changed it in the meantime, the undo is refused
+// with a conflict message instead of destroying data. Overwritten files are
+// trashed first (a Trash entry before the Copy or Move that replaced them)
+
+#[derive(Debug, Clone)]
+pub enum Operation {
+    Move {
+        from: PathBuf,
+        to: PathBuf,
+    }, // moves and renames
+    Copy {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Mkdir {
+        path: PathBuf,
+    },
+    // a new file, created as a copy of the template if there is one
+    Touch {
+        path: PathBuf,
+        template: Option<PathBuf>,
+    },
+    // a symlink (with target as its content) or a hardlink to target
+    Link {
+        target: PathBuf,
+        path: PathBuf,
+        hard: bool,
+    },
+    // member of archive ("" for all of it) extracted into the directory to
+    Extract {
+        archive: PathBuf,
+        member: PathBuf,
+        to: PathBuf,
+    },
+    // a new archive of the sources
+    Compress {
+        sources: Vec<PathBuf>,
+        to: PathBuf,
+    },
+    // to is the file in the trash, info its .trashinfo file
+    Trash {
+        from: PathBuf,
+        to: PathBuf,
+        info: PathBuf,
+    },
+}
+
+impl Operation {
+    // the path the operation produced
+    fn result(&self) -> &Path {
+        match self {
+            Operation::Move { to, .. } | Operation::Copy { to, .. } => to,
+            Operation::Trash { to, .. } => to,
+            Operation::Mkdir { path } | Operation::Touch { path, .. } => path,
+            Operation::Link { path, .. } => path,
+            Operation::Extract { to, .. } | Operation::Compress { to, .. } => to,
+        }
+    }
+}
+
+// what a path looked like right after an operation
+#[derive(Debug, Clone, PartialEq)]
+struct Stamp {
+    is_dir: bool,
+    len: u64,
+    modified: Option<SystemTime>,
+    // a hash of everything inside of a directory, see of_tree
+    tree: Option<u64>,
+}
+
+impl Stamp {
+    fn of(pathb: &Path) -> Option<Self> {
+        let metadata = pathb.symlink_metadata().ok()?;
+        Some(Stamp {
+            is_dir: metadata.is_dir(),
+            // the size of a directory says nothing about its content
+            len: if metadata.is_dir() { 0 } else { metadata.len() },
+            modified: metadata.modified().ok(),
+            tree: None,
+        })
+    }
+
+    // for the copies and extractions that an undo removes with everything
+    // inside, a file edited deep down doesn't change the top directory
+    fn of_tree(pathb: &Path) -> Option<Self> {
+        let mut stamp = Self::of(pathb)?;
+        if stamp.is_dir {
+            let mut hasher = DefaultHasher::new();
+            hash_tree(pathb, &mut hasher);
+            stamp.tree = Some(hasher.finish());
+        }
+        Some(stamp)
+    }
+}
+
+fn hash_tree(dir: &Path, hasher: &mut DefaultHasher) {
+    let mut entries = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .collect::<Vec<_>>();
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        entry.file_name().hash(hasher);
+        if let Some(stamp) = Stamp::of(&entry.path()) {
+            (stamp.len, stamp.modified).hash(hasher);
+            if stamp.is_dir {
+                hash_tree(&entry.path(), hasher);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    operation: Operation,
+    stamp: Option<Stamp>,
+}
+
+// everything one action did, undone and redone as a whole
+#[derive(Debug, Clone)]
+pub struct Record {
+    description: String,
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug, Default)]
+pub struct Journal {
+    undo: Vec<Record>,
+    redo: Vec<Record>,
+}
+
+impl Journal {
+    // remembers the operations of one action, a new action makes the
+    // undone ones unredoable
+    pub fn record(&mut self, description: &str, operations: Vec<Operation>) {
+        if operations.is_empty() {
+            return;
+        }
+        let entries = operations
+            .into_iter()
+            .map(|operation| Entry {
+                stamp: match operation {
+                    Operation::Copy { .. } | Operation::Extract { .. } => {
+                        Stamp::of_tree(operation.result())
+                    }
+                    _ => Stamp::of(operation.result()),
+                },
+                operation,
+            })
+            .collect();
+        self.undo.push(Record {
+            description: description.to_string(),
+            entries,
+        });
+        self.redo.clear();
+    }
+
+    // reverts the last action, returns a message for the status bar
+    pub fn undo(&mut self) -> Result<String, String> {
+        let record = self.undo.pop().ok_or("nothing to undo")?;
+        if let Err(conflict) = record
+            .entries
+            .iter()
+            .try_for_each(|e| check_undo(e, &record))
+        {
+            let msg = format!("can't undo {}: {}", record.description, conflict);
+            self.undo.push(record);
+            return Err(msg);
+        }
+        // all moves are reverted at once, they might swap names
+        let moves = record
+            .moves()
+            .map(|(from, to)| (to, from))
+            .collect::<Vec<_>>();
+        let result = file_ops::move_all(&moves).and_then(|()| {
+            record
+                .entries
+                .iter()
+                .rev()
+                .try_for_each(|entry| apply_undo(&entry.operation))
+        });
+        if let Err(error) = result {
+            // the part that was reverted already stays reverted
+            return Err(format!("undo of {} failed: {}", record.description, error));
+        }
+        let redone = record
+            .entries
+            .iter()
+            .map(|entry| Entry {
+                operation: entry.operation.clone(),
+                stamp: undo_stamp(&entry.operation),
+            })
+            .collect();
+        let msg = format!("undid {}", record.description);
+        self.redo.push(Record {
+            description: record.description,
+            entries: redone,
+        });
+        Ok(msg)
+    }
+
+    // repeats the last undone action, returns a message for the status bar
+    pub fn redo(&mut self) -> Result<String, String> {
+        let record = self.redo.pop().ok_or("nothing to redo")?;
+        if let Err(conflict) = record
+            .entries
+            .iter()
+            .try_for_each(|e| check_redo(e, &record))
+        {
+            let msg = format!("can't redo {}: {}", record.description, conflict);
+            self.redo.push(record);
+            return Err(msg);
+        }
+        // trashing comes first, it makes room for the files that replaced
+        // the trashed ones, then all moves at once, then the rest
+        let (trashed, rest): (Vec<&Entry>, Vec<&Entry>) = record
+            .entries
+            .iter()
+            .partition(|entry| matches!(entry.operation, Operation::Trash { .. }));
+        let mut operations = Vec::new();
+        for entry in trashed {
+            match apply_redo(&entry.operation) {
+                Ok(operation) => operations.push(operation),
+                Err(error) => {
+                    return Err(format!("redo of {} failed: {}", record.description, error))
+                }
+            }
+        }
+        let moves = record.moves().collect::<Vec<_>>();
+        if let Err(error) = file_ops::move_all(&moves) {
+            return Err(format!("redo of {} failed: {}", record.description, error));
+        }
+        for entry in rest {
+            match apply_redo(&entry.operation) {
+                Ok(operation) => operations.push(operation),
+                Err(error) => {
+                    return Err(format!("redo of {} failed: {}", record.description, error))
+                }
+            }
+        }
+        let redo = std::mem::take(&mut self.redo);
+        self.record(&record.description, operations);
+        self.redo = redo;
+        Ok(format!("redid {}", record.description))
+    }
+}
+
+impl Record {
+    // the (from, to) pairs of all moves of the record
+    fn moves(&self) -> impl Iterator<Item = (PathBuf, PathBuf)> + '_ {
+        self.entries
+            .iter()
+            .filter_map(|entry| match &entry.operation {
+                Operation::Move { from, to } => Some((from.clone(), to.clone())),
+                _ => None,
+            })
+    }
+
+    // whether a move of the record frees up the path (like in a swap)
+    fn moves_away(&self, pathb: &Path) -> bool {
+        self.moves().any(|(from, _)| from == pathb)
+    }
+
+    fn moves_to(&self, pathb: &Path) -> bool {
+        self.moves().any(|(_, to)| to == pathb)
+    }
+
+    // whether the record trashed the path to replace it
+    fn trashes(&self, pathb: &Path) -> bool {
+        self.entries.iter().any(|entry| match &entry.operation {
+            Operation::Trash { from, .. } => from == pathb,
+            _ => false,
+        })
+    }
+
+    // whether a copy, move or link of the record put something at the path
+    fn produces(&self, pathb: &Path) -> bool {
+        self.entries.iter().any(|entry| match &entry.operation {
+            Operation::Move { to, .. } | Operation::Copy { to, .. } => to == pathb,
+            Operation::Link { path, .. } => path == pathb,
+            _ => false,
+        })
+    }
+}
+
+// the stamp of the path an undo restored, checked before a redo
+fn undo_stamp(operation: &Operation) -> Option<Stamp> {
+    match operation {
+        Operation::Move { from, .. } | Operation::Trash { from, .. } => Stamp::of(from),
+        Operation::Copy { from, .. } => Stamp::of(from),
+        Operation::Extract { archive, .. } => Stamp::of(archive),
+        // the sources are archived as they are at the time of the redo
+        Operation::Compress { .. } => None,
+        Operation::Mkdir { .. } | Operation::Touch { .. } | Operation::Link { .. } => None,
+    }
+}
+
+fn unchanged(pathb: &Path, stamp: &Option<Stamp>) -> Result<(), String> {
+    let current = match stamp {
+        Some(Stamp { tree: Some(_), .. }) => Stamp::of_tree(pathb),
+        _ => Stamp::of(pathb),
+    };
+    match current {
+        None => Err(format!("{} doesn't exist anymore", pathb.display())),
+        Some(current) if stamp.as_ref() != Some(&current) => {
+            Err(format!("{} changed since the operation", pathb.display()))
+        }
+        Some(_) => Ok(()),
+    }
+}
+
+fn free(pathb: &Path) -> Result<(), String> {
+    if pathb.symlink_metadata().is_ok() {
+        Err(format!("{} exists again", pathb.display()))
+    } else {
+        Ok(())
+    }
+}
+
+fn check_undo(entry: &Entry, record: &Record) -> Result<(), String> {
+    match &entry.operation {
+        Operation::Move { from, to } => unchanged(to, &entry.stamp).and_then(|()| {
+            if record.moves_to(from) {
+                Ok(())
+            } else {
+                free(from)
+            }
+        }),
+        Operation::Copy { to, .. } => unchanged(to, &entry.stamp),
+        Operation::Mkdir { path } | Operation::Touch { path, .. } => unchanged(path, &entry.stamp),
+        Operation::Link { path, .. } => unchanged(path, &entry.stamp),
+        Operation::Extract { to, .. } | Operation::Compress { to, .. } => {
+            unchanged(to, &entry.stamp)
+        }
+        // the replacement is removed before the trashed file comes back
+        Operation::Trash { from, to, .. } => unchanged(to, &entry.stamp).and_then(|()| {
+            if record.produces(from) {
+                Ok(())
+            } else {
+                free(from)
+            }
+        }),
+    }
+}
+
+fn check_redo(entry: &Entry, record: &Record) -> Result<(), String> {
+    match &entry.operation {
+        Operation::Move { from, to } => unchanged(from, &entry.stamp).and_then(|()| {
+            if record.moves_away(to) || record.trashes(to) {
+                Ok(())
+            } else {
+                free(to)
+            }
+        }),
+        Operation::Copy { from, to } => unchanged(from, &entry.stamp).and_then(|()| {
+            if record.trashes(to) {
+                Ok(())
+            } else {
+                free(to)
+            }
+        }),
+        Operation::Mkdir { path } | Operation::Touch { path, .. } => free(path),
+        Operation::Link { path, .. } => {
+            if record.trashes(path) {
+                Ok(())
+            } else {
+                free(path)
+            }
+        }
+        Operation::Extract { archive, to, .. } => {
+            unchanged(archive, &entry.stamp).and_then(|()| free(to))
+        }
+        Operation::Compress { to, .. } => free(to),
+        Operation::Trash { from, .. } => unchanged(from, &entry.stamp),
+    }
+}
+
+// moves are left out, they are reverted all at once beforehand
+fn apply_undo(operation: &Operation) -> Result<(), std::io::Error> {
+    match operation {
+        Operation::Move { .. } => Ok(()),
+        Operation::Copy { to, .. } => file_ops::remove_path(to),
+        // only an empty directory is removed, just like rmdir
+        Operation::Mkdir { path } => fs::remove_dir(path),
+        Operation::Touch { path, .. } | Operation::Link { path, .. } => fs::remove_file(path),
+        Operation::Extract { to, .. } => file_ops::remove_path(to),
+        Operation::Compress { to, .. } => fs::remove_file(to),
+        Operation::Trash { from, to, info } => trash::restore(&TrashItem {
+            original: from.clone(),
+            deleted: String::new(),
+            file: to.clone(),
+            info: info.clone(),
+        }),
+    }
+}
+
+// repeats the operation, returns it as performed this time. Moves are
+// left out, they are repeated all at once beforehand
+fn apply_redo(operation: &Operation) -> Result<Operation, std::io::Error> {
+    let ctx = JobContext::default();
+    match operation {
+        Operation::Move { .. } => Ok(()),
+        Operation::Copy { from, to } => file_ops::copy_tree(from, to, &ctx),
+        Operation::Mkdir { path } => fs::create_dir(path),
+        Operation::Touch { path, template } => file_ops::create_file(path, template.as_deref()),
+        Operation::Link { target, path, hard } => file_ops::create_link(target, path, *hard),
+        Operation::Extract {
+            archive,
+            member,
+            to,
+        } => archive::extract(archive, member, to, &ctx),
+        Operation::Compress { sources, to } => archive::create(to, sources, &ctx),
+        Operation::Trash { from, .. } => {
+            // the file might get another name in the trash this time
+            let item = trash::trash_path(from, &ctx)?;
+            return Ok(Operation::Trash {
+                from: item.original,
+                to: item.file,
+                info: item.info,
+            });
+        }
+    }?;
+    Ok(operation.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a fresh directory with the given files, the tests run in parallel
+    fn test_dir(name: &str, files: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "mampf-test-journal-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        for file in files {
+            let pathb = dir.join(file);
+            fs::create_dir_all(pathb.parent().unwrap()).unwrap();
+            fs::write(pathb, file).unwrap();
+        }
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn moved(journal: &mut Journal, from: &Path, to: &Path) {
+        fs::rename(from, to).unwrap();
+        let operation = Operation::Move {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        };
+        journal.record("move", vec![operation]);
+    }
+
+    fn copied(journal: &mut Journal, from: &Path, to: &Path) {
+        file_ops::copy_tree(from, to, &JobContext::default()).unwrap();
+        let operation = Operation::Copy {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        };
+        journal.record("copy", vec![operation]);
+    }
+
+    fn append(pathb: &Path, text: &str) {
+        let content = fs::read_to_string(pathb).unwrap();
+        fs::write(pathb, content + text).unwrap();
+    }
+
+    #[test]
+    fn undo_redo_round_trip() {
+        let dir = test_dir("round-trip", &["a", "d/f"]);
+        let mut journal = Journal::default();
+        moved(&mut journal, &dir.join("a"), &dir.join("b"));
+        copied(&mut journal, &dir.join("d"), &dir.join("e"));
+        assert!(journal.undo().is_ok());
+        assert!(!dir.join("e").exists());
+        assert!(journal.undo().is_ok());
+        assert_eq!(fs::read_to_string(dir.join("a")).unwrap(), "a");
+        assert!(!dir.join("b").exists());
+        assert!(journal.undo().is_err());
+        assert!(journal.redo().is_ok());
+        assert!(journal.redo().is_ok());
+        assert_eq!(fs::read_to_string(dir.join("b")).unwrap(), "a");
+        assert_eq!(fs::read_to_string(dir.join("e/f")).unwrap(), "d/f");
+        assert!(journal.redo().is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn undo_of_modified_move_is_refused() {
+        let dir = test_dir("move", &["a"]);
+        let mut journal = Journal::default();
+        moved(&mut journal, &dir.join("a"), &dir.join("b"));
+        append(&dir.join("b"), " edited");
+        assert!(journal.undo().is_err());
+        assert!(!dir.join("a").exists());
+        assert_eq!(fs::read_to_string(dir.join("b")).unwrap(), "a edited");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn undo_of_edited_copy_is_refused() {
+        let dir = test_dir("copy", &["d/sub/f"]);
+        let mut journal = Journal::default();
+        copied(&mut journal, &dir.join("d"), &dir.join("e"));
+        // deep down, the top directory doesn't change
+        append(&dir.join("e/sub/f"), " edited");
+        assert!(journal.undo().is_err());
+        assert_eq!(
+            fs::read_to_string(dir.join("e/sub/f")).unwrap(),
+            "d/sub/f edited"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn redo_onto_recreated_path_is_refused() {
+        let dir = test_dir("redo", &["a"]);
+        let mut journal = Journal::default();
+        moved(&mut journal, &dir.join("a"), &dir.join("b"));
+        assert!(journal.undo().is_ok());
+        fs::write(dir.join("b"), "new").unwrap();
+        assert!(journal.redo().is_err());
+        assert_eq!(fs::read_to_string(dir.join("a")).unwrap(), "a");
+        assert_eq!(fs::read_to_string(dir.join("b")).unwrap(), "new");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -3,6 +3,8 @@ use termion::event::Key;
 
 use crate::{
     config::Config,
+    file_ops::LinkKind,
+    shell::ShellCommand,
     util::{Filter, SortBy},
 };
 
@@ -15,14 +17,45 @@ pub enum Action {
     In,
     Out,
     Quit,
+    QuitNoCd,
+    Choose,
     Mark,
     MarkAll,
     UnMark,
     UnMarkAll,
+    Copy,
+    Move,
+    Yank,
+    Cut,
+    Paste,
+    SelectRegister,
+    Delete,
+    Trash,
+    ShowTrash,
+    Rename,
+    BulkRename,
+    Mkdir,
+    Touch,
+    Chmod,
+    Extract,
+    Compress,
+    DirSizes,
+    DiskUsage,
+    Duplicates,
+    Compare,
+    Checksum,
+    VerifyChecksums,
+    ToggleCrossFs,
+    Link(LinkKind),
+    Jobs,
+    Undo,
+    Redo,
     Jump(PathBuf),
     ToggleFilter(Filter),
     DoSortBy(SortBy),
-    ShellCmd(String),
+    ShellCmd(ShellCommand),
+    ShowOutput,
+    CommandLine,
     TUICmd(String),
 }
 
@@ -62,7 +95,7 @@ impl KeyState {
         let mut actions = Vec::new();
         // check if the key is a number, inc the number_tracker and return
         if let Key::Char(num) = key {
-            if num.is_digit(10) {
+            if num.is_ascii_digit() {
                 if self.number_tracker != 1 {
                     self.number_tracker *= 10;
                 }
@@ -75,7 +108,7 @@ impl KeyState {
         // add the action as often as number_tracker says to
         // temporary solution since it doesn't support leader keys yet
         for keybind in self.config.keybindings.clone() {
-            if keybind.keys.len() == 1 && keybind.keys.get(0).unwrap_or(&Key::Null) == &key {
+            if keybind.keys.len() == 1 && keybind.keys.first().unwrap_or(&Key::Null) == &key {
                 // let mut actions = Vec::new();
                 while self.number_tracker > 1 {
                     actions.push(keybind.action.clone());
@@ -1,30 +1,90 @@
-use std::process::Command;
+use std::{
+    collections::{HashMap, VecDeque},
+    ffi::OsString,
+    io::Write,
+    process::Command,
+    time::Duration,
+};
 
 pub mod app;
+pub mod archive;
+pub mod bulk_rename;
+pub mod checksum;
+pub mod cli;
+pub mod command_line;
+pub mod compare;
 pub mod config;
+pub mod dialogs;
+pub mod du;
+pub mod duplicates;
+pub mod file_ops;
 pub mod fm_state;
+pub mod jobs;
+pub mod journal;
 pub mod keys;
+pub mod registers;
+pub mod shell;
+pub mod trash;
 pub mod ui;
 pub mod util;
+pub mod vfs;
 
 use app::App;
+use cli::Cli;
 use config::Config;
 use keys::{Action, KeyState};
+use registers::ClipMode;
+use shell::ShellCommand;
+use termion::event::Key;
 use ui::terminal_ui::TerminalUI;
 use ui::UI;
 
 pub fn main() -> Result<(), std::io::Error> {
-    let config = Config::new().expect("Coudln't parse config file.");
+    let args = match cli::parse(std::env::args().skip(1)) {
+        Ok(Cli::Run(args)) => args,
+        Ok(Cli::Help) => {
+            println!("{}", cli::USAGE);
+            return Ok(());
+        }
+        Ok(Cli::Version) => {
+            println!("mampf {}", env!("CARGO_PKG_VERSION"));
+            return Ok(());
+        }
+        Err(msg) => {
+            eprintln!("mampf: {}\nTry 'mampf --help' for more information.", msg);
+            std::process::exit(2);
+        }
+    };
+    let config = Config::new(args.config.as_deref()).expect("Coudln't parse config file.");
     let mut keystate = KeyState::new(config.clone());
-    let mut state = App::from(config);
+    let stdout = if args.choose_to_stdout() {
+        Some(util::redirect_stdout_to_tty()?)
+    } else {
+        None
+    };
+    let mut state = App::from(config, args);
     let mut mytui: TerminalUI = TerminalUI::init().expect("Couldn't initalize TUI backend");
 
     // main loop
     while !state.is_exit() {
+        state.update_jobs();
+        if std::mem::take(&mut state.show_output) {
+            dialogs::show_output(&mut state, &mut mytui)?;
+        }
         mytui.refresh(&state).expect("Couldn't refresh");
-        let keypress = mytui.get_next_keypress();
-        let actions = keystate.press(keypress);
-        for action in actions {
+        // while jobs are running, the UI is refreshed to show their progress
+        let keypress = if state.is_busy() {
+            match mytui.poll_keypress(Some(Duration::from_millis(250))) {
+                Some(keypress) => keypress,
+                None => continue,
+            }
+        } else {
+            mytui.get_next_keypress()
+        };
+        state.message = None;
+        // actions typed on the command line are queued behind it
+        let mut actions = VecDeque::from(keystate.press(keypress));
+        while let Some(action) = actions.pop_front() {
             match action {
                 Action::Up => {
                     state.fm_state.move_up();
@@ -33,7 +93,15 @@ pub fn main() -> Result<(), std::io::Error> {
                     state.fm_state.move_down();
                 }
                 Action::In => {
-                    state.fm_state.move_in();
+                    let on_file = state
+                        .fm_state
+                        .get_focused()
+                        .is_some_and(|f| f.exists() && !f.is_dir());
+                    if state.args.is_picker() && on_file {
+                        dialogs::choose(&mut state, &mut mytui)?;
+                    } else {
+                        state.fm_state.move_in();
+                    }
                 }
                 Action::Out => {
                     state.fm_state.move_out();
@@ -45,7 +113,29 @@ pub fn main() -> Result<(), std::io::Error> {
                     state.fm_state.unmark_current();
                 }
                 Action::Quit => {
-                    state.fm_state.exit();
+                    if dialogs::confirm_quit(&mut state, &mut mytui)? {
+                        state.fm_state.exit();
+                    }
+                }
+                Action::Choose => {
+                    if state.args.is_picker() {
+                        dialogs::choose(&mut state, &mut mytui)?;
+                    }
+                }
+                Action::QuitNoCd => {
+                    if dialogs::confirm_quit(&mut state, &mut mytui)? {
+                        state.cd_on_exit = false;
+                        state.fm_state.exit();
+                    }
+                }
+                Action::Undo => {
+                    state.undo();
+                }
+                Action::Redo => {
+                    state.redo();
+                }
+                Action::Jobs => {
+                    dialogs::show_jobs(&mut state, &mut mytui)?;
                 }
                 Action::MarkAll => {
                     execute_tui("nvim", &mut mytui)?;
@@ -53,6 +143,135 @@ pub fn main() -> Result<(), std::io::Error> {
                 Action::UnMarkAll => {
                     state.fm_state.unmark_all();
                 }
+                Action::Yank => {
+                    state.yank_selection(ClipMode::Yank);
+                }
+                Action::Cut => {
+                    state.yank_selection(ClipMode::Cut);
+                }
+                Action::Paste => match state.registers.get().cloned() {
+                    Some(register) => {
+                        let sources = &register.paths;
+                        if let Some(transfers) =
+                            dialogs::resolve_conflicts(&mut state, &mut mytui, sources)?
+                        {
+                            state.paste(register.mode, transfers);
+                        }
+                    }
+                    None => {
+                        let active = state.registers.active;
+                        state.message = Some(format!("register {} is empty", active));
+                    }
+                },
+                Action::SelectRegister => {
+                    // like in vim, the next key names the register
+                    if let Key::Char(name) = mytui.get_next_keypress() {
+                        state.registers.active = name;
+                        state.message = Some(
+                            state
+                                .registers
+                                .describe()
+                                .unwrap_or_else(|| format!("register {} is empty", name)),
+                        );
+                    }
+                }
+                Action::Copy => {
+                    let marked = state.fm_state.get_marked();
+                    if let Some(transfers) =
+                        dialogs::resolve_conflicts(&mut state, &mut mytui, &marked)?
+                    {
+                        state.copy_transfers(transfers);
+                    }
+                }
+                Action::Move => {
+                    let marked = state.fm_state.get_marked();
+                    if let Some(transfers) =
+                        dialogs::resolve_conflicts(&mut state, &mut mytui, &marked)?
+                    {
+                        state.move_transfers(transfers);
+                    }
+                }
+                Action::Delete => {
+                    let count = state.fm_state.get_selection().len();
+                    let question = format!("Delete {} entries permanently?", count);
+                    if count > 0 && mytui.confirm(&state, &question)? {
+                        state.delete_selection();
+                    }
+                }
+                Action::Trash => {
+                    state.trash_selection();
+                }
+                Action::ShowTrash => {
+                    dialogs::show_trash(&mut state, &mut mytui)?;
+                }
+                Action::Rename => {
+                    let new_name = mytui.get_user_input(&state, "Rename to: ")?;
+                    if !new_name.is_empty() {
+                        state.rename_focused(&new_name);
+                    }
+                }
+                Action::BulkRename => {
+                    dialogs::bulk_rename(&mut state, &mut mytui)?;
+                }
+                Action::Mkdir => {
+                    let name = mytui.get_user_input(&state, "New directory: ")?;
+                    if !name.is_empty() {
+                        state.mkdir(&name);
+                    }
+                }
+                Action::Touch => {
+                    let name = mytui.get_user_input(&state, "New file: ")?;
+                    if !name.is_empty() {
+                        state.touch(&name);
+                    }
+                }
+                Action::Link(kind) => {
+                    let marked = state.fm_state.get_marked();
+                    if let Some(transfers) =
+                        dialogs::resolve_conflicts(&mut state, &mut mytui, &marked)?
+                    {
+                        state.link_transfers(transfers, kind);
+                    }
+                }
+                Action::Chmod => {
+                    dialogs::edit_permissions(&mut state, &mut mytui)?;
+                }
+                Action::DirSizes => {
+                    state.request_dir_sizes();
+                }
+                Action::Compare => {
+                    dialogs::compare_dirs(&mut state, &mut mytui)?;
+                }
+                Action::Checksum => {
+                    dialogs::show_checksums(&mut state, &mut mytui)?;
+                }
+                Action::VerifyChecksums => {
+                    dialogs::verify_checksums(&mut state, &mut mytui)?;
+                }
+                Action::Duplicates => {
+                    dialogs::find_duplicates(&mut state, &mut mytui)?;
+                }
+                Action::DiskUsage => {
+                    dialogs::disk_usage(&mut state, &mut mytui)?;
+                }
+                Action::ToggleCrossFs => {
+                    let cross = !du::crosses_filesystems();
+                    du::set_cross_filesystems(cross);
+                    state.message = Some(if cross {
+                        "directory sizes include other file systems".to_string()
+                    } else {
+                        "directory sizes stay on one file system".to_string()
+                    });
+                }
+                Action::Extract => {
+                    state.extract_selection();
+                }
+                Action::Compress => {
+                    let name = mytui.get_user_input(&state, "Archive name: ")?;
+                    if !name.is_empty() {
+                        state.compress(&name);
+                    }
+                }
                 Action::Jump(pathb) => {
                     state.fm_state.jump_to(pathb);
                 }
@@ -63,94 +282,49 @@ pub fn main() -> Result<(), std::io::Error> {
                     state.fm_state.set_sortby(sortby);
                 }
                 Action::ShellCmd(cmd) => {
-                    execute_cmd(&cmd, &state, &mut mytui);
+                    shell::execute_cmd(&cmd, &mut state, &mut mytui)?;
+                }
+                Action::CommandLine => {
+                    if let Some(action) = dialogs::command_line(&mut state, &mut mytui)? {
+                        actions.push_back(action);
+                    }
+                }
+                Action::ShowOutput => {
+                    dialogs::show_output(&mut state, &mut mytui)?;
                 }
                 Action::TUICmd(cmd) => {
                     execute_tui(&cmd, &mut mytui)?;
+                    du::invalidate();
                 }
             }
         }
     }
-    Ok(())
+    // restore the terminal before anything is written to the real stdout
+    drop(mytui);
+    std::io::stdout().flush()?;
+    state.write_choosedir()?;
+    state.write_chosen(stdout)
 }
 
 pub fn execute_tui(cmd: &str, tui: &mut TerminalUI) -> Result<(), std::io::Error> {
-    let split = cmd.split(' ');
-    let mut parts = split.collect::<Vec<&str>>();
-    parts.reverse();
-    let main = parts.pop().unwrap();
-    parts.reverse();
-    tui.tui_app_start()?;
-    let mut cmd_temp = Command::new(main);
-    let command = cmd_temp.args(parts.iter());
-    command.spawn()?.wait()?;
-    tui.tui_app_end()?;
-    Ok(())
+    let command = split_command(cmd)?;
+    run_tui(&command, tui)
 }
 
-pub fn execute_cmd(cmd: &str, state: &App, ui: &mut impl UI) -> Option<()> {
-    let commands = format_command(state, cmd, ui)?;
-    for command in commands {
-        execute_one_cmd(&command)?;
-    }
-    Some(())
+// splits the command into its words like the shell would
+pub fn split_command(cmd: &str) -> Result<Vec<OsString>, std::io::Error> {
+    shell::expand(&ShellCommand::new(cmd), &HashMap::new())
+        .map_err(|msg| std::io::Error::new(std::io::ErrorKind::InvalidInput, msg))
 }
 
-fn format_command(state: &App, cmd: &str, ui: &mut impl UI) -> Option<Vec<String>> {
-    let fm_state = &state.fm_state;
-    let mut commands = Vec::new();
-    let mut new_cmd = String::new();
-
-    // handle %f (current file) and %d (current directory)
-    match &fm_state.get_focused() {
-        Some(focused_pathb) => {
-            let filename = focused_pathb.file_name()?.to_str()?;
-            new_cmd = cmd.replace("%f", filename);
-        }
-        None => {
-            if cmd.contains("%f") || cmd.contains("%d") {
-                return None;
-            }
-        }
-    }
-    let directory = fm_state.get_currentdir();
-    let directory = directory.to_str()?;
-    new_cmd = new_cmd.replace("%d", directory);
-
-    // handle %i (input by the user)
-    if new_cmd.contains("%i") {
-        let input = ui.get_user_input(&state, "Input: ").ok()?;
-        new_cmd = new_cmd.replace("%i", &input);
-        println!("{}", new_cmd);
-        execute_one_cmd(&("notify-send ".to_string() + &new_cmd));
-    }
-
-    // handle %F (all marked files) and %D (all corresponding marked directories)
-    if new_cmd.contains("%F") || new_cmd.contains("%D") {
-        for marked_path in fm_state.get_marked().iter() {
-            let filename = marked_path.file_name()?.to_str()?;
-            let directory = marked_path.parent()?.to_str()?;
-            let temp_cmd = new_cmd.replace("%F", filename).replace("%D", directory);
-            commands.push(temp_cmd);
-        }
-    } else {
-        commands.push(new_cmd);
-    }
-
-    Some(commands)
-}
-
-fn execute_one_cmd(cmd: &str) -> Option<()> {
-    // TODO more parsing needed to allow for grouping everything in parens
-    // into one arg. Currently 'notify-send 'for example'' would parse 'for
-    // and example' into two args
-    let split = cmd.split(' ');
-    let mut parts = split.collect::<Vec<&str>>();
-    parts.reverse();
-    let main = parts.pop()?;
-    parts.reverse();
-    let mut cmd_temp = Command::new(main);
-    let command = cmd_temp.args(parts.iter());
-    let _output = command.output().ok()?;
-    Some(())
+// runs the already split command on the terminal
+pub fn run_tui(command: &[OsString], tui: &mut TerminalUI) -> Result<(), std::io::Error> {
+    let (main, args) = match command.split_first() {
+        Some(split) => split,
+        None => return Ok(()),
+    };
+    tui.tui_app_start()?;
+    Command::new(main).args(args).spawn()?.wait()?;
+    tui.tui_app_end()?;
+    Ok(())
 }
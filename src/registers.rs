@@ -0,0 +1,70 @@
+use std::{collections::HashMap, path::PathBuf};
+
+// Registers work like a clipboard: yank and cut put paths into the active
+// register, paste copies or moves them into the current directory. Like in
+// vim, there are as many registers as there are characters, '"' is the
+// default one
+
+pub const DEFAULT_REGISTER: char = '"';
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClipMode {
+    Yank, // paste copies
+    Cut,  // paste moves
+}
+
+#[derive(Debug, Clone)]
+pub struct Register {
+    pub mode: ClipMode,
+    pub paths: Vec<PathBuf>,
+}
+
+#[derive(Debug)]
+pub struct Registers {
+    registers: HashMap<char, Register>,
+    pub active: char,
+}
+
+impl Default for Registers {
+    fn default() -> Self {
+        Registers {
+            registers: HashMap::new(),
+            active: DEFAULT_REGISTER,
+        }
+    }
+}
+
+impl Registers {
+    pub fn set(&mut self, mode: ClipMode, paths: Vec<PathBuf>) {
+        self.registers.insert(self.active, Register { mode, paths });
+    }
+
+    pub fn get(&self) -> Option<&Register> {
+        self.registers.get(&self.active)
+    }
+
+    // empties the register once its cut paths were moved, unless something
+    // else was put into it in the meantime
+    pub fn clear_cut(&mut self, name: char, paths: &[PathBuf]) {
+        if let Some(register) = self.registers.get(&name) {
+            if register.mode == ClipMode::Cut && register.paths == paths {
+                self.registers.remove(&name);
+            }
+        }
+    }
+
+    // e.g. "a: cut 3" for the status bar, None if the register is empty
+    pub fn describe(&self) -> Option<String> {
+        let register = self.get()?;
+        let mode = match register.mode {
+            ClipMode::Yank => "yanked",
+            ClipMode::Cut => "cut",
+        };
+        Some(format!(
+            "\"{}: {} {}",
+            self.active,
+            mode,
+            register.paths.len()
+        ))
+    }
+}
@@ -0,0 +1,608 @@
+use std::{
+    collections::HashMap,
+    ffi::{OsStr, OsString},
+    os::unix::{
+        ffi::{OsStrExt, OsStringExt},
+        process::{CommandExt, ExitStatusExt},
+    },
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use crate::{
+    app::App,
+    ui::{terminal_ui::TerminalUI, UI},
+};
+
+// The shell commands of the cmd bindings. A command is split into words the
+// way a POSIX shell does it: whitespace separates words, '...' is taken
+// literally, "..." keeps whitespace and a backslash escapes the next
+// character. Placeholders like %f are replaced after the splitting, so a
+// file name with spaces or quotes always stays a single argument.
+// Bindings with the option "shell" run their command with sh -c instead
+// (for pipelines and redirections), there the values get quoted for sh.
+// Commands block the UI until they are done unless they are run in the
+// background or detached (like GUI programs, those may outlive mampf)
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShellCommand {
+    pub template: String,
+    pub shell: bool, // run with sh -c
+    pub output: ShowOutput,
+    pub mode: ExecMode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExecMode {
+    Tui,      // on the terminal, like execute_tui
+    Blocking, // the output is collected
+    Background,
+    Detached, // in a new session without stdio, never waited for
+}
+
+// when the output of a command is shown in a popup
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShowOutput {
+    Never, // not even the result in the status bar
+    OnError,
+    Always,
+}
+
+impl ShellCommand {
+    pub fn new(template: &str) -> Self {
+        ShellCommand {
+            template: template.to_string(),
+            shell: false,
+            output: ShowOutput::OnError,
+            mode: ExecMode::Blocking,
+        }
+    }
+}
+
+// what the commands of a binding printed and how they ended
+#[derive(Debug, Clone, Default)]
+pub struct CommandOutput {
+    pub program: String,
+    pub lines: Vec<(String, bool)>, // (line, whether it went to stderr)
+    // how the first failed command ended, None if all succeeded
+    pub error: Option<String>,
+}
+
+impl CommandOutput {
+    // a line for the status bar, the error or the last line of the output
+    pub fn summary(&self) -> String {
+        let last_line = self
+            .lines
+            .iter()
+            .rev()
+            .find(|(line, is_stderr)| !line.trim().is_empty() && self.error.is_some() == *is_stderr)
+            .or_else(|| self.lines.last())
+            .map(|(line, _)| line.trim());
+        match (&self.error, last_line) {
+            (Some(error), Some(line)) => format!("{} failed ({}): {}", self.program, error, line),
+            (Some(error), None) => format!("{} failed ({})", self.program, error),
+            (None, Some(line)) => format!("{}: {}", self.program, line),
+            (None, None) => format!("{} done", self.program),
+        }
+    }
+}
+
+// runs the command the way its mode says, the result shows up in the
+// status bar (right away or, in the background, once it is done)
+pub fn execute_cmd(
+    cmd: &ShellCommand,
+    state: &mut App,
+    tui: &mut TerminalUI,
+) -> Result<(), std::io::Error> {
+    let commands = match format_command(state, cmd, tui) {
+        Ok(commands) => commands,
+        Err(msg) => {
+            state.message = Some(format!("can't run {}: {}", cmd.template, msg));
+            return Ok(());
+        }
+    };
+    let dir = state.fm_state.get_currentdir();
+    match cmd.mode {
+        ExecMode::Tui => {
+            let mut output = CommandOutput::default();
+            tui.tui_app_start()?;
+            for command in commands {
+                run_in_terminal(&command, &dir, &mut output);
+            }
+            tui.tui_app_end()?;
+            state.finish_command(cmd.output, output);
+        }
+        ExecMode::Blocking => {
+            let mut output = CommandOutput::default();
+            for command in commands {
+                execute_one_cmd(&command, &dir, &mut output);
+            }
+            state.finish_command(cmd.output, output);
+        }
+        ExecMode::Background => {
+            let background = Background {
+                show: cmd.output,
+                output: Arc::new(Mutex::new(None)),
+            };
+            let thread_output = background.output.clone();
+            thread::spawn(move || {
+                let mut output = CommandOutput::default();
+                for command in commands {
+                    execute_one_cmd(&command, &dir, &mut output);
+                }
+                if let Ok(mut thread_output) = thread_output.lock() {
+                    *thread_output = Some(output);
+                }
+            });
+            state.background.push(background);
+        }
+        ExecMode::Detached => {
+            for command in commands {
+                if let Err(error) = detach(&command, &dir) {
+                    state.message = Some(format!("can't run {}: {}", cmd.template, error));
+                    break;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// a command running in the background, its output is handed over once done
+pub struct Background {
+    pub show: ShowOutput,
+    output: Arc<Mutex<Option<CommandOutput>>>,
+}
+
+impl Background {
+    // the output, once the command is done
+    pub fn take_output(&self) -> Option<CommandOutput> {
+        self.output.lock().ok()?.take()
+    }
+}
+
+// the program and arguments of every command to run, there is one per
+// marked file if %F or %D is used. Fails if a placeholder has no value
+fn format_command(
+    state: &App,
+    cmd: &ShellCommand,
+    ui: &mut impl UI,
+) -> Result<Vec<Vec<OsString>>, String> {
+    let fm_state = &state.fm_state;
+    let used = placeholders(&cmd.template);
+    let mut values = HashMap::new();
+    let no_name = || String::from("a path without a name");
+
+    // handle %f (current file), %p (its full path), %n (its name without
+    // the extension), %e (its extension) and %d (current directory)
+    if let Some(focused_pathb) = fm_state.get_focused() {
+        let extension = focused_pathb.extension().unwrap_or_default();
+        let stem = focused_pathb.file_stem().ok_or_else(no_name)?;
+        values.insert('n', vec![stem.to_os_string()]);
+        values.insert('e', vec![extension.to_os_string()]);
+        let filename = focused_pathb.file_name().ok_or_else(no_name)?;
+        values.insert('f', vec![filename.to_os_string()]);
+        values.insert('p', vec![focused_pathb.into_os_string()]);
+    } else if used.iter().any(|c| "fpne".contains(*c)) {
+        return Err(String::from("no file focused"));
+    }
+    values.insert('d', vec![fm_state.get_currentdir().into_os_string()]);
+
+    // handle %o (the directory last compared with)
+    if let Some(other_dir) = &state.other_dir {
+        values.insert('o', vec![other_dir.as_os_str().to_os_string()]);
+    } else if used.contains(&'o') {
+        return Err(String::from("no directory compared with yet"));
+    }
+
+    // handle %S (the full paths of all marked files, or the focused one, in
+    // a single command) and %c (the number of marked files)
+    let selection = fm_state.get_selection();
+    values.insert(
+        'S',
+        selection.into_iter().map(PathBuf::into_os_string).collect(),
+    );
+    values.insert('c', vec![fm_state.get_marked().len().to_string().into()]);
+
+    // handle %i (input by the user)
+    if used.contains(&'i') {
+        let input = ui
+            .get_user_input(state, "Input: ")
+            .map_err(|e| e.to_string())?;
+        values.insert('i', vec![OsString::from(input)]);
+    }
+
+    // handle %F (all marked files) and %D (all corresponding marked directories)
+    let mut commands = Vec::new();
+    if used.contains(&'F') || used.contains(&'D') {
+        for marked_path in fm_state.get_marked().iter() {
+            let filename = marked_path.file_name().ok_or_else(no_name)?;
+            values.insert('F', vec![filename.to_os_string()]);
+            let parent = marked_path.parent().ok_or_else(no_name)?;
+            values.insert('D', vec![parent.as_os_str().to_os_string()]);
+            commands.push(expand(cmd, &values)?);
+        }
+    } else {
+        commands.push(expand(cmd, &values)?);
+    }
+    Ok(commands)
+}
+
+// commands run in the current directory (unless that is inside an archive),
+// what they print is collected in output
+fn execute_one_cmd(command: &[OsString], dir: &Path, output: &mut CommandOutput) {
+    let (main, args) = match command.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    set_program(output, main);
+    let mut command = Command::new(main);
+    if dir.is_dir() {
+        command.current_dir(dir);
+    }
+    // the terminal belongs to the ui, a command waiting for input would hang
+    let result = match command.args(args).stdin(Stdio::null()).output() {
+        Ok(result) => result,
+        Err(error) => {
+            output.error.get_or_insert_with(|| error.to_string());
+            return;
+        }
+    };
+    for (bytes, is_stderr) in [(&result.stdout, false), (&result.stderr, true)] {
+        let text = String::from_utf8_lossy(bytes).replace('\t', "    ");
+        output
+            .lines
+            .extend(text.lines().map(|line| (line.to_string(), is_stderr)));
+    }
+    record_status(output, result.status);
+}
+
+// the first program run names the output
+fn set_program(output: &mut CommandOutput, main: &OsStr) {
+    if output.program.is_empty() {
+        let program = Path::new(main).file_name().unwrap_or(main);
+        output.program = program.to_string_lossy().into_owned();
+    }
+}
+
+fn record_status(output: &mut CommandOutput, status: ExitStatus) {
+    if !status.success() {
+        let error = match (status.code(), status.signal()) {
+            (Some(code), _) => format!("exit status {}", code),
+            (None, Some(signal)) => format!("killed by signal {}", signal),
+            (None, None) => String::from("failed"),
+        };
+        output.error.get_or_insert(error);
+    }
+}
+
+// runs a command with the terminal as its stdio, only how it ended is
+// collected in output
+fn run_in_terminal(command: &[OsString], dir: &Path, output: &mut CommandOutput) {
+    let (main, args) = match command.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    set_program(output, main);
+    let mut command = Command::new(main);
+    if dir.is_dir() {
+        command.current_dir(dir);
+    }
+    match command.args(args).status() {
+        Ok(status) => record_status(output, status),
+        Err(error) => {
+            output.error.get_or_insert_with(|| error.to_string());
+        }
+    }
+}
+
+// starts a command in its own session without stdio, so that it neither
+// draws over the UI nor dies with mampf. A thread waits for it to end
+fn detach(command: &[OsString], dir: &Path) -> Result<(), std::io::Error> {
+    let (main, args) = match command.split_first() {
+        Some(split) => split,
+        None => return Ok(()),
+    };
+    let mut command = Command::new(main);
+    if dir.is_dir() {
+        command.current_dir(dir);
+    }
+    command
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // setsid is async-signal-safe, so it can be called after the fork
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let mut child = command.spawn()?;
+    thread::spawn(move || child.wait());
+    Ok(())
+}
+
+// the program and its arguments, the placeholders replaced by the values
+pub fn expand(
+    cmd: &ShellCommand,
+    values: &HashMap<char, Vec<OsString>>,
+) -> Result<Vec<OsString>, String> {
+    if cmd.shell {
+        let script = quote_values(&cmd.template, values)?;
+        Ok(vec!["sh".into(), "-c".into(), script])
+    } else {
+        split_words(&cmd.template, values)
+    }
+}
+
+// the placeholders used outside of single quotes
+fn placeholders(template: &str) -> Vec<char> {
+    let mut used = Vec::new();
+    let mut quote = None;
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\'', None) => quote = Some('\''),
+            ('"', None) => quote = Some('"'),
+            (c, Some(open)) if c == open => quote = None,
+            ('\\', None) | ('\\', Some('"')) => {
+                chars.next();
+            }
+            ('%', None) | ('%', Some('"')) => used.extend(chars.next()),
+            _ => {}
+        }
+    }
+    used
+}
+
+// a placeholder with several values, like %S, turns its word into one word
+// per value (with the rest of the word repeated)
+fn split_words(
+    template: &str,
+    values: &HashMap<char, Vec<OsString>>,
+) -> Result<Vec<OsString>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<Vec<Vec<u8>>> = None;
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                words.extend(word.take().into_iter().flatten().map(OsString::from_vec))
+            }
+            '\'' => {
+                let word = word.get_or_insert_with(|| vec![Vec::new()]);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => push_char(word, c),
+                        None => return Err(String::from("unterminated quote")),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(|| vec![Vec::new()]);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        // only these characters can be escaped in double quotes
+                        Some('\\') if matches!(chars.peek(), Some('"' | '\\' | '$' | '`')) => {
+                            push_char(word, chars.next().unwrap_or('\\'));
+                        }
+                        Some('%') => push_placeholder(word, &mut chars, values),
+                        Some(c) => push_char(word, c),
+                        None => return Err(String::from("unterminated quote")),
+                    }
+                }
+            }
+            '\\' => push_char(
+                word.get_or_insert_with(|| vec![Vec::new()]),
+                chars.next().unwrap_or('\\'),
+            ),
+            '%' => push_placeholder(
+                word.get_or_insert_with(|| vec![Vec::new()]),
+                &mut chars,
+                values,
+            ),
+            c => push_char(word.get_or_insert_with(|| vec![Vec::new()]), c),
+        }
+    }
+    words.extend(word.into_iter().flatten().map(OsString::from_vec));
+    if words.is_empty() {
+        return Err(String::from("empty command"));
+    }
+    Ok(words)
+}
+
+// appends the character to every variant of the word
+fn push_char(word: &mut [Vec<u8>], c: char) {
+    for variant in word.iter_mut() {
+        variant.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+    }
+}
+
+// a % followed by a placeholder with a value, %% is a single % and other %s
+// are kept
+fn push_placeholder(
+    word: &mut Vec<Vec<u8>>,
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+    values: &HashMap<char, Vec<OsString>>,
+) {
+    if chars.next_if_eq(&'%').is_some() {
+        push_char(word, '%');
+        return;
+    }
+    match chars.peek().and_then(|c| values.get(c)) {
+        Some(value) => {
+            chars.next();
+            *word = word
+                .iter()
+                .flat_map(|variant| {
+                    value
+                        .iter()
+                        .map(move |value| [variant.as_slice(), value.as_bytes()].concat())
+                })
+                .collect();
+        }
+        None => push_char(word, '%'),
+    }
+}
+
+// the template for sh -c, the values are quoted for the place they are in
+fn quote_values(template: &str, values: &HashMap<char, Vec<OsString>>) -> Result<OsString, String> {
+    let mut script = Vec::new();
+    let mut quote = None;
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\'', None) => quote = Some('\''),
+            ('"', None) => quote = Some('"'),
+            (c, Some(open)) if c == open => quote = None,
+            ('\\', None) | ('\\', Some('"')) => {
+                let escaped = chars.next().map(String::from).unwrap_or_default();
+                script.extend_from_slice(format!("{}{}", c, escaped).as_bytes());
+                continue;
+            }
+            ('%', None) | ('%', Some('"')) => {
+                if chars.next_if_eq(&'%').is_some() {
+                    script.push(b'%');
+                    continue;
+                }
+                if let Some(value) = chars.peek().and_then(|c| values.get(c)) {
+                    chars.next();
+                    for (idx, value) in value.iter().enumerate() {
+                        if idx > 0 {
+                            script.push(b' ');
+                        }
+                        quote_value(&mut script, value, quote.is_some());
+                    }
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        script.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+    }
+    if quote.is_some() {
+        return Err(String::from("unterminated quote"));
+    }
+    Ok(OsString::from_vec(script))
+}
+
+// in double quotes only ", \, $ and ` are special, elsewhere the value gets
+// single quotes (with every ' in it as '\'')
+fn quote_value(script: &mut Vec<u8>, value: &OsStr, in_double_quotes: bool) {
+    if in_double_quotes {
+        for byte in value.as_bytes() {
+            if matches!(byte, b'"' | b'\\' | b'$' | b'`') {
+                script.push(b'\\');
+            }
+            script.push(*byte);
+        }
+    } else {
+        script.push(b'\'');
+        for byte in value.as_bytes() {
+            if *byte == b'\'' {
+                script.extend_from_slice(b"'\\''");
+            } else {
+                script.push(*byte);
+            }
+        }
+        script.push(b'\'');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(pairs: &[(char, &[&str])]) -> HashMap<char, Vec<OsString>> {
+        pairs
+            .iter()
+            .map(|(c, values)| (*c, values.iter().map(OsString::from).collect()))
+            .collect()
+    }
+
+    fn words(template: &str, values: &HashMap<char, Vec<OsString>>) -> Vec<String> {
+        split_words(template, values)
+            .unwrap()
+            .into_iter()
+            .map(|word| word.into_string().unwrap())
+            .collect()
+    }
+
+    fn script(template: &str, values: &HashMap<char, Vec<OsString>>) -> String {
+        quote_values(template, values)
+            .unwrap()
+            .into_string()
+            .unwrap()
+    }
+
+    #[test]
+    fn split_words_quotes_and_escapes() {
+        let none = HashMap::new();
+        assert_eq!(words("  ls  -l\t/tmp ", &none), ["ls", "-l", "/tmp"]);
+        assert_eq!(words("echo 'a  b' \"c d\"", &none), ["echo", "a  b", "c d"]);
+        assert_eq!(words("echo a'b'\"c\"", &none), ["echo", "abc"]);
+        assert_eq!(words("echo '' \"\"", &none), ["echo", "", ""]);
+        assert_eq!(words(r"echo a\ b \'", &none), ["echo", "a b", "'"]);
+        assert_eq!(words(r#"echo "\"\\\$\a""#, &none), ["echo", r#""\$\a"#]);
+        assert_eq!(words(r"echo 'it\'", &none), ["echo", r"it\"]);
+    }
+
+    #[test]
+    fn split_words_errors() {
+        let none = HashMap::new();
+        assert!(split_words("echo 'a", &none).is_err());
+        assert!(split_words("echo \"a", &none).is_err());
+        assert!(split_words("   ", &none).is_err());
+    }
+
+    #[test]
+    fn split_words_placeholders() {
+        let values = values(&[('f', &["my file"]), ('d', &["/a dir"])]);
+        assert_eq!(words("cat %f", &values), ["cat", "my file"]);
+        assert_eq!(words("cp %f %d/x", &values), ["cp", "my file", "/a dir/x"]);
+        assert_eq!(words("echo \"<%f>\"", &values), ["echo", "<my file>"]);
+        // no placeholders in single quotes
+        assert_eq!(words("echo '%f'", &values), ["echo", "%f"]);
+        assert_eq!(
+            words("echo 100%% %x %", &values),
+            ["echo", "100%", "%x", "%"]
+        );
+    }
+
+    #[test]
+    fn split_words_multiple_values() {
+        let values = values(&[('S', &["a", "b c"]), ('d', &["/x"])]);
+        assert_eq!(words("rm %S", &values), ["rm", "a", "b c"]);
+        assert_eq!(
+            words("echo %d/%S.bak", &values),
+            ["echo", "/x/a.bak", "/x/b c.bak"]
+        );
+        assert_eq!(words("echo \"-%S\"", &values), ["echo", "-a", "-b c"]);
+        let nothing = self::values(&[('S', &[])]);
+        assert_eq!(words("ls %S", &nothing), ["ls"]);
+    }
+
+    #[test]
+    fn quote_values_for_the_shell() {
+        let values = values(&[('f', &["it's $HOME"])]);
+        assert_eq!(script("wc -l %f", &values), r"wc -l 'it'\''s $HOME'");
+        assert_eq!(script("echo \"<%f>\"", &values), r#"echo "<it's \$HOME>""#);
+        assert_eq!(script("echo '%f'", &values), "echo '%f'");
+        assert_eq!(script(r"echo \%f", &values), r"echo \%f");
+        assert_eq!(script("echo 100%% > x", &values), "echo 100% > x");
+        assert!(quote_values("echo \"%f", &values).is_err());
+        let values = self::values(&[('S', &["a", "b c"])]);
+        assert_eq!(
+            script("tar czf x.tgz %S", &values),
+            "tar czf x.tgz 'a' 'b c'"
+        );
+    }
+}
@@ -0,0 +1,331 @@
+use std::{
+    fs,
+    io::{Error, ErrorKind, Write},
+    os::unix::{
+        ffi::{OsStrExt, OsStringExt},
+        fs::{MetadataExt, PermissionsExt},
+    },
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    file_ops,
+    jobs::JobContext,
+    journal::Operation,
+    util::{format_time, home_dir},
+};
+
+// A native implementation of the FreeDesktop.org trash specification
+// (https://specifications.freedesktop.org/trash-spec/trashspec-latest.html).
+// Files in the home file system go to $XDG_DATA_HOME/Trash, files on other
+// mounts to $topdir/.Trash/$uid or $topdir/.Trash-$uid. Every trashed file
+// lives in files/ with a matching .trashinfo file in info/ that remembers
+// where it came from and when it was deleted
+
+#[derive(Debug, Clone)]
+pub struct TrashItem {
+    pub original: PathBuf, // where the file was trashed from
+    pub deleted: String,   // the deletion date as written in the info file
+    pub file: PathBuf,     // the trashed file in files/
+    pub info: PathBuf,     // the .trashinfo file in info/
+}
+
+// trashes all given paths, every trashed path is journaled so it can be restored
+pub fn trash(paths: &[PathBuf], ctx: &JobContext) -> file_ops::OpReport {
+    let mut report = file_ops::OpReport::default();
+    ctx.add_total(0, paths.len() as u64);
+    for pathb in paths {
+        if let Err(error) = ctx.checkpoint() {
+            report.failed.push((pathb.clone(), error));
+            break;
+        }
+        match trash_path(pathb, ctx) {
+            Ok(item) => {
+                report.done.push(item.file.clone());
+                report.operations.push(Operation::Trash {
+                    from: item.original,
+                    to: item.file,
+                    info: item.info,
+                });
+            }
+            Err(error) => report.failed.push((pathb.clone(), error)),
+        }
+        ctx.file_done();
+    }
+    report
+}
+
+// moves one path into the matching trash directory
+pub fn trash_path(pathb: &Path, ctx: &JobContext) -> Result<TrashItem, Error> {
+    file_ops::check_writable(pathb)?;
+    let original = absolute_path(pathb)?;
+    let name = original
+        .file_name()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "can't trash the root"))?
+        .to_os_string();
+    let (trash_dir, topdir) = trash_dir_for(&original)?;
+    let files_dir = trash_dir.join("files");
+    let info_dir = trash_dir.join("info");
+    fs::create_dir_all(&files_dir)?;
+    fs::create_dir_all(&info_dir)?;
+
+    // the info file is created exclusively first, it reserves the name
+    // against other programs trashing a file with the same name
+    let mut counter = 1;
+    let (file, info, mut info_file) = loop {
+        let mut candidate = name.clone();
+        if counter > 1 {
+            candidate.push(format!(".{}", counter));
+        }
+        let mut info_name = candidate.clone();
+        info_name.push(".trashinfo");
+        let info = info_dir.join(&info_name);
+        let file = files_dir.join(&candidate);
+        if file.symlink_metadata().is_err() {
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&info)
+            {
+                Ok(info_file) => break (file, info, info_file),
+                Err(error) if error.kind() == ErrorKind::AlreadyExists => {}
+                Err(error) => return Err(error),
+            }
+        }
+        counter += 1;
+    };
+
+    // paths in trash directories of other mounts are stored relative to
+    // the top directory, so that they stay valid if it's mounted elsewhere
+    let stored_path = match &topdir {
+        Some(topdir) => original.strip_prefix(topdir).unwrap_or(&original),
+        None => &original,
+    };
+    let deleted = local_timestamp();
+    let content = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        url_encode(stored_path),
+        deleted
+    );
+    let result = info_file
+        .write_all(content.as_bytes())
+        .and_then(|()| file_ops::move_path(&original, &file, ctx));
+    if let Err(error) = result {
+        let _ = fs::remove_file(&info);
+        return Err(error);
+    }
+    Ok(TrashItem {
+        original,
+        deleted,
+        file,
+        info,
+    })
+}
+
+// moves a trashed file back to where it came from
+pub fn restore(item: &TrashItem) -> Result<(), Error> {
+    if item.original.symlink_metadata().is_ok() {
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            format!("{} already exists", item.original.display()),
+        ));
+    }
+    if let Some(parent) = item.original.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    file_ops::move_path(&item.file, &item.original, &JobContext::default())?;
+    fs::remove_file(&item.info)
+}
+
+// removes a trashed file for good
+pub fn purge(item: &TrashItem) -> Result<(), Error> {
+    if item.file.symlink_metadata().is_ok() {
+        file_ops::remove_path(&item.file)?;
+    }
+    fs::remove_file(&item.info)
+}
+
+// all items of all trash directories that can be found, newest first
+pub fn list() -> Vec<TrashItem> {
+    let mut items = Vec::new();
+    items.extend(list_dir(&home_trash(), None));
+    for topdir in mount_points() {
+        for trash_dir in topdir_trash_dirs(&topdir) {
+            if trash_dir != home_trash() {
+                items.extend(list_dir(&trash_dir, Some(&topdir)));
+            }
+        }
+    }
+    items.sort_by(|x, y| y.deleted.cmp(&x.deleted));
+    items
+}
+
+fn list_dir(trash_dir: &Path, topdir: Option<&Path>) -> Vec<TrashItem> {
+    let entries = match fs::read_dir(trash_dir.join("info")) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut items = Vec::new();
+    for entry in entries.flatten() {
+        let info = entry.path();
+        if info.extension().and_then(|ext| ext.to_str()) != Some("trashinfo") {
+            continue;
+        }
+        let content = match fs::read_to_string(&info) {
+            Ok(content) => content,
+            Err(_) => continue,
+        };
+        let mut original = None;
+        let mut deleted = String::new();
+        for line in content.lines() {
+            if let Some(path) = line.strip_prefix("Path=") {
+                original = Some(url_decode(path));
+            } else if let Some(date) = line.strip_prefix("DeletionDate=") {
+                deleted = date.to_string();
+            }
+        }
+        let original = match (original, topdir) {
+            (Some(path), Some(topdir)) if path.is_relative() => topdir.join(path),
+            (Some(path), _) => path,
+            (None, _) => continue,
+        };
+        let file = trash_dir
+            .join("files")
+            .join(info.file_stem().unwrap_or_default());
+        items.push(TrashItem {
+            original,
+            deleted,
+            file,
+            info,
+        });
+    }
+    items
+}
+
+fn home_trash() -> PathBuf {
+    match std::env::var("XDG_DATA_HOME") {
+        Ok(data_home) if !data_home.is_empty() => PathBuf::from(data_home).join("Trash"),
+        _ => home_dir().join(".local").join("share").join("Trash"),
+    }
+}
+
+// the trash directory for a path together with the top directory of its
+// mount, if it isn't the home trash
+fn trash_dir_for(pathb: &Path) -> Result<(PathBuf, Option<PathBuf>), Error> {
+    let home_trash = home_trash();
+    let home_dev = nearest_existing(&home_trash)?.metadata()?.dev();
+    let dev = pathb.symlink_metadata()?.dev();
+    if dev == home_dev {
+        return Ok((home_trash, None));
+    }
+    let topdir = topdir_of(pathb, dev);
+    let uid = unsafe { libc::getuid() };
+    // $topdir/.Trash is only used if the admin prepared it properly
+    let admin_trash = topdir.join(".Trash");
+    if let Ok(metadata) = admin_trash.symlink_metadata() {
+        let sticky = metadata.permissions().mode() & 0o1000 != 0;
+        if metadata.is_dir() && sticky {
+            let user_trash = admin_trash.join(uid.to_string());
+            if fs::create_dir_all(&user_trash).is_ok() {
+                return Ok((user_trash, Some(topdir)));
+            }
+        }
+    }
+    let user_trash = topdir.join(format!(".Trash-{}", uid));
+    match fs::create_dir(&user_trash) {
+        Ok(()) => {
+            fs::set_permissions(&user_trash, fs::Permissions::from_mode(0o700))?;
+            Ok((user_trash, Some(topdir)))
+        }
+        Err(error) if error.kind() == ErrorKind::AlreadyExists => Ok((user_trash, Some(topdir))),
+        // fall back to the home trash, the file is copied over then
+        Err(_) => Ok((home_trash, None)),
+    }
+}
+
+fn topdir_trash_dirs(topdir: &Path) -> Vec<PathBuf> {
+    let uid = unsafe { libc::getuid() };
+    vec![
+        topdir.join(".Trash").join(uid.to_string()),
+        topdir.join(format!(".Trash-{}", uid)),
+    ]
+}
+
+// walks up from pathb as long as the device stays the same
+fn topdir_of(pathb: &Path, dev: u64) -> PathBuf {
+    let mut topdir = pathb.to_path_buf();
+    for ancestor in pathb.ancestors().skip(1) {
+        match ancestor.metadata() {
+            Ok(metadata) if metadata.dev() == dev => topdir = ancestor.to_path_buf(),
+            _ => break,
+        }
+    }
+    topdir
+}
+
+fn nearest_existing(pathb: &Path) -> Result<&Path, Error> {
+    pathb
+        .ancestors()
+        .find(|ancestor| ancestor.exists())
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, "no existing parent"))
+}
+
+fn mount_points() -> Vec<PathBuf> {
+    let mounts = fs::read_to_string("/proc/mounts").unwrap_or_default();
+    mounts
+        .lines()
+        .filter_map(|line| line.split(' ').nth(1))
+        // /proc/mounts escapes spaces and co. as octal numbers
+        .map(|mount| PathBuf::from(mount.replace("\\040", " ").replace("\\011", "\t")))
+        .collect()
+}
+
+fn absolute_path(pathb: &Path) -> Result<PathBuf, Error> {
+    // the parent is resolved, the file itself isn't (it might be a symlink)
+    let parent = pathb.parent().unwrap_or_else(|| Path::new("/"));
+    let name = pathb.file_name().unwrap_or_default();
+    Ok(parent.canonicalize()?.join(name))
+}
+
+fn url_encode(pathb: &Path) -> String {
+    let mut encoded = String::new();
+    for byte in pathb.as_os_str().as_bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(*byte as char)
+            }
+            _ => encoded += &format!("%{:02X}", byte),
+        }
+    }
+    encoded
+}
+
+fn url_decode(encoded: &str) -> PathBuf {
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::new();
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let hex = encoded.get(idx + 1..idx + 3);
+        match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+            Some(byte) if bytes[idx] == b'%' => {
+                decoded.push(byte);
+                idx += 3;
+            }
+            _ => {
+                decoded.push(bytes[idx]);
+                idx += 1;
+            }
+        }
+    }
+    PathBuf::from(std::ffi::OsString::from_vec(decoded))
+}
+
+// the current local time as YYYY-MM-DDThh:mm:ss
+fn local_timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or(0);
+    format_time(now).replace(' ', "T")
+}
@@ -1,4 +1,5 @@
 use crate::app::App;
+use std::time::Duration;
 use termion::event::Key;
 
 pub mod terminal_ui;
@@ -10,11 +11,32 @@ pub trait UI {
         Self: Sized;
 
     // There should be the possibility to get String input from the user
-    fn get_user_input(&mut self, state: &App, question: &str) -> Result<String, std::io::Error>;
+    fn get_user_input(&mut self, state: &App, question: &str) -> Result<String, std::io::Error> {
+        self.get_user_input_with(state, question, &mut |_, _| {})
+    }
+
+    // Like get_user_input, but the keys that don't edit the input (like tab
+    // or up) are handed to on_key, which can change the input
+    fn get_user_input_with(
+        &mut self,
+        state: &App,
+        question: &str,
+        on_key: &mut dyn FnMut(Key, &mut String),
+    ) -> Result<String, std::io::Error>;
+
+    // Asks a yes/no question, everything but 'y' or 'yes' counts as no
+    fn confirm(&mut self, state: &App, question: &str) -> Result<bool, std::io::Error> {
+        let answer = self.get_user_input(state, &format!("{} [y/N] ", question))?;
+        Ok(answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes"))
+    }
 
     // For keybindings there should be a function that returns the next keypress
     fn get_next_keypress(&mut self) -> Key;
 
+    // Like get_next_keypress, but gives up after timeout (None waits forever)
+    // so that the UI can be refreshed while background work is going on
+    fn poll_keypress(&mut self, timeout: Option<Duration>) -> Option<Key>;
+
     // Refreshes the UI based on the current state
     fn refresh(&mut self, state: &App) -> Result<(), std::io::Error>;
 }
@@ -1,9 +1,10 @@
 use crate::{
     app::App,
+    du,
     ui::UI,
-    util::{get_size, EntryStyle, PaneContent, PaneRole},
+    util::{format_bytes, get_size, EntryStyle, PaneContent, PaneRole, Popup},
 };
-use std::{io::Stdout, path::PathBuf};
+use std::{collections::VecDeque, io::Stdout, path::Path, time::Duration};
 use termion::{
     event::Key,
     input::TermRead,
@@ -14,13 +15,14 @@ use tui::{
     backend::{Backend, TermionBackend},
     layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
-    widgets::{Block, BorderType, Borders, List, ListState, Paragraph, Text},
+    widgets::{Block, BorderType, Borders, Clear, Gauge, List, ListState, Paragraph, Text},
     Frame, Terminal,
 };
 
 pub struct TerminalUI {
     pub liststate: ListState,
     pub input_state: Option<String>,
+    pending_keys: VecDeque<Key>, // keys that were read but not handled yet
     pub terminal: Terminal<TermionBackend<AlternateScreen<RawTerminal<Stdout>>>>,
 }
 
@@ -38,16 +40,23 @@ impl UI for TerminalUI {
         Ok(TerminalUI {
             liststate,
             input_state: None,
+            pending_keys: VecDeque::new(),
             terminal,
         })
     }
 
-    fn get_user_input(&mut self, state: &App, question: &str) -> Result<String, std::io::Error> {
+    fn get_user_input_with(
+        &mut self,
+        state: &App,
+        question: &str,
+        on_key: &mut dyn FnMut(Key, &mut String),
+    ) -> Result<String, std::io::Error> {
         let mut input = String::new();
         self.input_state = Some(question.to_string());
         self.refresh(state)?;
         loop {
             match self.get_next_keypress() {
+                Key::Char('\t') => on_key(Key::Char('\t'), &mut input),
                 Key::Char(c) => {
                     if c == '\n' {
                         break;
@@ -58,7 +67,14 @@ impl UI for TerminalUI {
                 Key::Backspace => {
                     input.pop();
                 }
-                _ => {}
+                Key::Esc => {
+                    // an empty input cancels the question
+                    input.clear();
+                    break;
+                }
+                // stdin got closed
+                Key::Null => break,
+                key => on_key(key, &mut input),
             }
             self.input_state = Some(question.to_string() + &input);
             self.refresh(state)?;
@@ -68,15 +84,40 @@ impl UI for TerminalUI {
     }
 
     fn get_next_keypress(&mut self) -> Key {
-        let stdin = std::io::stdin();
-        for keypress in stdin.keys() {
-            if let Ok(keypress) = keypress {
-                return keypress;
-            }
+        // waits until a key is pressed, Key::Null is only returned once
+        // stdin is closed
+        self.poll_keypress(None).unwrap_or(Key::Null)
+    }
+
+    fn poll_keypress(&mut self, timeout: Option<Duration>) -> Option<Key> {
+        if let Some(key) = self.pending_keys.pop_front() {
+            return Some(key);
         }
-        // The function will be stuck in the loop until a key is pressed.
-        // This return statement shouldn't come into play
-        Key::Null
+        let timeout = timeout.map_or(-1, |timeout| timeout.as_millis() as libc::c_int);
+        let mut pollfd = libc::pollfd {
+            fd: libc::STDIN_FILENO,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        if unsafe { libc::poll(&mut pollfd, 1, timeout) } <= 0 {
+            return None;
+        }
+        // everything available is read at once and parsed into keys, so that
+        // no bytes of fast typing or pasting get lost in between
+        let mut buffer = [0u8; 1024];
+        let read = unsafe {
+            libc::read(
+                libc::STDIN_FILENO,
+                buffer.as_mut_ptr() as *mut libc::c_void,
+                buffer.len(),
+            )
+        };
+        if read <= 0 {
+            return None;
+        }
+        let bytes = &buffer[..read as usize];
+        self.pending_keys.extend(bytes.keys().flatten());
+        self.pending_keys.pop_front()
     }
 
     fn refresh(&mut self, state: &App) -> Result<(), std::io::Error> {
@@ -93,11 +134,30 @@ impl UI for TerminalUI {
         let mut liststate = ListState::default();
         liststate.select(state.get_idx());
 
-        let text = if let Some(input) = &self.input_state {
-            input.clone()
-        } else {
-            get_size(state.get_current_fm_state().get_focused())
+        // the left side of the status bar shows the input prompt or the last
+        // message, the right side the active register and the size of the
+        // focused entry
+        let status = match (&self.input_state, &state.message) {
+            (Some(input), _) => input.clone(),
+            (None, Some(message)) => message.clone(),
+            (None, None) => String::new(),
+        };
+        // running jobs replace the message with a progress bar
+        let progress = match state.jobs.current() {
+            Some(job) if self.input_state.is_none() => {
+                let others = state.jobs.jobs.len() - 1;
+                let mut label = job.describe();
+                if others > 0 {
+                    label += &format!(" (+{} jobs)", others);
+                }
+                Some((job.ratio(), label))
+            }
+            _ => None,
         };
+        let mut text = get_size(state.get_current_fm_state().get_focused());
+        if let Some(register) = state.registers.describe() {
+            text = format!("{}  {}", register, text);
+        }
 
         self.terminal.draw(|mut f| {
             // TODO should probably move a good bit of widgeting out
@@ -112,23 +172,44 @@ impl UI for TerminalUI {
             }
             let vertical_split = Layout::default()
                 .direction(Direction::Horizontal)
-                .constraints(contraints.as_ref())
+                .constraints(contraints.as_slice())
                 .split(horizontal_split[0]);
 
+            let status_split = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(80), Constraint::Percentage(20)].as_ref())
+                .split(horizontal_split[1]);
+            if let Some((ratio, label)) = &progress {
+                let gauge = Gauge::default()
+                    .block(textpane)
+                    .style(Style::default().fg(Color::Cyan).bg(Color::Black))
+                    .ratio(*ratio)
+                    .label(label);
+                f.render_widget(gauge, status_split[0]);
+            } else {
+                let status_text = [Text::raw(status)];
+                let status_box = Paragraph::new(status_text.iter())
+                    .block(textpane)
+                    .alignment(Alignment::Left)
+                    .wrap(false);
+                f.render_widget(status_box, status_split[0]);
+            }
+
             let info_text = Text::raw(text);
             let text_arr = [info_text];
             let info_box = Paragraph::new(text_arr.iter())
                 .block(textpane)
                 .alignment(Alignment::Right)
                 .wrap(false);
-            f.render_widget(info_box, horizontal_split[1]);
+            f.render_widget(info_box, status_split[1]);
             // Self::render_content(&mut f, state.get_content_right(), vertical_split[2], pane);
             // Self::render_content(&mut f, state.get_content_left(), vertical_split[0], pane);
             for (idx, pane_config) in state.config.panes.iter().enumerate() {
                 match &pane_config.role {
                     PaneRole::Current => {
+                        let width = vertical_split[idx].width;
                         if let Some(list) =
-                            Self::create_current_widget(state.get_content_middle(), pane)
+                            Self::create_current_widget(state.get_content_middle(), pane, width)
                         {
                             f.render_stateful_widget(list, vertical_split[idx], &mut liststate);
                         }
@@ -143,6 +224,9 @@ impl UI for TerminalUI {
                     }
                 }
             }
+            if let Some(popup) = &state.popup {
+                Self::render_popup(&mut f, popup, pane);
+            }
         })?;
         Ok(())
     }
@@ -170,16 +254,43 @@ impl TerminalUI {
         }
     }
 
+    // draws the popup centered on top of everything else
+    fn render_popup<B: Backend>(f: &mut Frame<B>, popup: &Popup, pane: Block) {
+        let size = f.size();
+        let rect = Rect::new(
+            size.width / 8,
+            size.height / 6,
+            size.width * 3 / 4,
+            size.height * 2 / 3,
+        );
+        let mut liststate = ListState::default();
+        liststate.select(popup.selected);
+        let list = List::new(
+            popup
+                .lines
+                .iter()
+                .map(|(line, style)| Text::styled(format!(" {}", line), Self::style_of(style))),
+        )
+        .block(pane.title(&popup.title))
+        .highlight_symbol(" > ")
+        .highlight_style(Style::default().fg(Color::Red).modifier(Modifier::BOLD));
+        f.render_widget(Clear, rect);
+        f.render_stateful_widget(list, rect, &mut liststate);
+    }
+
     fn create_current_widget(
         content: PaneContent,
         pane: Block<'static>,
+        width: u16,
     ) -> Option<List<'static, std::vec::IntoIter<Text<'static>>>> {
+        // the borders and the highlight symbol take 5 columns
+        let width = (width as usize).saturating_sub(5);
         match content {
             PaneContent::DirElements(ele_vec) => Some(
                 List::new(
                     ele_vec
                         .iter()
-                        .map(|x| Self::translate_style(&x.0, &x.1))
+                        .map(|x| Self::translate_entry(&x.0, &x.1, width))
                         .collect::<Vec<Text>>()
                         .into_iter(),
                 )
@@ -192,7 +303,7 @@ impl TerminalUI {
         }
     }
 
-    fn translate_style(pathb: &PathBuf, style: &EntryStyle) -> Text<'static> {
+    fn translate_style(pathb: &Path, style: &EntryStyle) -> Text<'static> {
         let mut filename = pathb
             .file_name()
             .unwrap()
@@ -200,13 +311,36 @@ impl TerminalUI {
             .into_string()
             .unwrap();
         filename = String::from(" ") + filename.as_str(); // cheap trick to get some padding to the left of the lists
+        Text::styled(filename, Self::style_of(style))
+    }
+
+    // like translate_style, with the size of directories whose size is
+    // known right aligned
+    fn translate_entry(pathb: &Path, style: &EntryStyle, width: usize) -> Text<'static> {
+        let name = pathb.file_name().unwrap_or_default().to_string_lossy();
+        let size = match du::usage(pathb) {
+            Some(usage) => format_bytes(usage.bytes),
+            None => return Self::translate_style(pathb, style),
+        };
+        // a leading space like in translate_style, at least one in between
+        let name_width = width.saturating_sub(size.len() + 2).max(1);
+        let name = name.chars().take(name_width).collect::<String>();
+        let padding = width.saturating_sub(name.chars().count() + size.len() + 1);
+        let text = format!(" {}{}{}", name, " ".repeat(padding.max(1)), size);
+        Text::styled(text, Self::style_of(style))
+    }
+
+    fn style_of(style: &EntryStyle) -> Style {
         match style {
             // Not complete yet, there might be a change once more customization is introduced
-            EntryStyle::Red => Text::styled(filename, Style::default().fg(Color::Red)),
-            EntryStyle::Blue => Text::styled(filename, Style::default().fg(Color::Blue)),
-            EntryStyle::Yellow => Text::styled(filename, Style::default().fg(Color::Yellow)),
-            EntryStyle::Cyan => Text::styled(filename, Style::default().fg(Color::Cyan)),
-            _ => Text::styled(filename, Style::default()),
+            EntryStyle::Red => Style::default().fg(Color::Red),
+            EntryStyle::Blue => Style::default().fg(Color::Blue),
+            EntryStyle::Yellow => Style::default().fg(Color::Yellow),
+            EntryStyle::Cyan => Style::default().fg(Color::Cyan),
+            EntryStyle::Green => Style::default().fg(Color::Green),
+            EntryStyle::Magenta => Style::default().fg(Color::Magenta),
+            EntryStyle::Gray => Style::default().fg(Color::Gray),
+            _ => Style::default(),
         }
     }
 
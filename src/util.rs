@@ -1,4 +1,14 @@
-use std::{path::PathBuf, time::SystemTime};
+use std::{
+    ffi::{CStr, CString},
+    fs::File,
+    io::{Read, Write},
+    os::unix::io::{AsRawFd, FromRawFd},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::SystemTime,
+};
+
+use crate::{du, vfs};
 
 // The filter struct, the sortby struct and some helper function can be found here
 
@@ -52,6 +62,7 @@ pub enum SortBy {
     LexioInc,
     LexioDec,
     New,
+    Size, // largest first
 }
 
 impl SortBy {
@@ -60,18 +71,19 @@ impl SortBy {
             SortBy::LexioInc => |x: &PathBuf, y: &PathBuf| {
                 x.to_str()
                     .unwrap()
-                    .partial_cmp(&y.to_str().unwrap())
+                    .partial_cmp(y.to_str().unwrap())
                     .unwrap()
             },
             SortBy::LexioDec => |x: &PathBuf, y: &PathBuf| {
                 y.to_str()
                     .unwrap()
-                    .partial_cmp(&x.to_str().unwrap())
+                    .partial_cmp(x.to_str().unwrap())
                     .unwrap()
             },
             SortBy::New => {
                 |x: &PathBuf, y: &PathBuf| get_modified(y).partial_cmp(&get_modified(x)).unwrap()
             }
+            SortBy::Size => |x: &PathBuf, y: &PathBuf| sort_size(y).cmp(&sort_size(x)),
         });
         list
     }
@@ -98,33 +110,265 @@ pub enum PaneContent {
     None,
 }
 
+// a list drawn on top of the panes, used by dialogs like the job list
+#[derive(Debug, Clone)]
+pub struct Popup {
+    pub title: String,
+    pub lines: Vec<(String, EntryStyle)>,
+    pub selected: Option<usize>,
+}
+
 // some usefull helper functions
 
+// the home directory of the user or the root directory as a fallback
+pub fn home_dir() -> PathBuf {
+    match std::env::var("HOME") {
+        Ok(val) => PathBuf::from(val),
+        Err(_e) => PathBuf::from("/"),
+    }
+}
+
+// the size of a file or the recursive size of a directory, which is
+// calculated in the background first ("dir" is shown until then)
 pub fn get_size(pathb: Option<PathBuf>) -> String {
     match pathb {
-        Some(pathbuf) => match std::fs::metadata(pathbuf) {
+        Some(pathbuf) => match std::fs::metadata(&pathbuf) {
             Ok(metadata) => {
                 if metadata.is_dir() {
-                    return String::from("dir");
-                }
-                match metadata.len() {
-                    0..=1000 => metadata.len().to_string() + "B",
-                    1001..=1000000 => (metadata.len() / 1000).to_string() + "KB",
-                    1000001..=1000000000 => (metadata.len() / 1000000).to_string() + "MB",
-                    _ => String::from("very large"),
+                    return match du::usage(&pathbuf) {
+                        Some(usage) => {
+                            format!("{}, {} items", format_bytes(usage.bytes), usage.items)
+                        }
+                        None => {
+                            du::request(&pathbuf);
+                            String::from("dir")
+                        }
+                    };
                 }
+                format_bytes(metadata.len())
             }
-            Err(_) => String::from("no metadata found"),
+            Err(_) => match vfs::member(&pathbuf) {
+                Some(member) if member.is_dir => format_bytes(vfs::tree_size(&pathbuf).0),
+                Some(member) => format_bytes(member.len),
+                None => String::from("no metadata found"),
+            },
         },
         None => String::from(""),
     }
 }
 
+// the size used for sorting, directories without a calculated size are
+// requested and count as empty until then
+fn sort_size(pathb: &Path) -> u64 {
+    match std::fs::metadata(pathb) {
+        Ok(metadata) if metadata.is_dir() => match du::usage(pathb) {
+            Some(usage) => usage.bytes,
+            None => {
+                du::request(pathb);
+                0
+            }
+        },
+        Ok(metadata) => metadata.len(),
+        Err(_) => match vfs::member(pathb) {
+            Some(member) if member.is_dir => vfs::tree_size(pathb).0,
+            Some(member) => member.len,
+            None => 0,
+        },
+    }
+}
+
+// reads the start of a text file (or a file inside of an archive) for the
+// preview pane, huge files would otherwise stall every refresh. Returns
+// None for binary files
+pub fn read_preview(pathb: &Path) -> Option<String> {
+    let mut buffer = Vec::new();
+    match File::open(pathb) {
+        Ok(file) => {
+            file.take(PREVIEW_BYTES).read_to_end(&mut buffer).ok()?;
+        }
+        Err(_) => buffer = vfs::read(pathb, PREVIEW_BYTES)?,
+    }
+    match String::from_utf8(buffer) {
+        Ok(text) => Some(text),
+        // a multi byte character might have been cut off at the end
+        Err(error) if error.utf8_error().error_len().is_none() => {
+            let valid = error.utf8_error().valid_up_to();
+            let mut buffer = error.into_bytes();
+            buffer.truncate(valid);
+            String::from_utf8(buffer).ok()
+        }
+        Err(_) => None,
+    }
+}
+
+const PREVIEW_BYTES: u64 = 64 * 1024;
+
+pub fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        0..=1000 => bytes.to_string() + "B",
+        1001..=1000000 => (bytes / 1000).to_string() + "KB",
+        1000001..=1000000000 => (bytes / 1000000).to_string() + "MB",
+        _ => format!("{:.1}GB", bytes as f64 / 1e9),
+    }
+}
+
+// seconds since the epoch broken down into local time
+pub fn local_time(secs: u64) -> libc::tm {
+    unsafe {
+        let time = secs as libc::time_t;
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&time, &mut tm);
+        tm
+    }
+}
+
+// seconds since the epoch as local time, YYYY-MM-DD hh:mm:ss
+pub fn format_time(secs: u64) -> String {
+    let tm = local_time(secs);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec
+    )
+}
+
+// the name of a user id or, if it has none, the id itself
+pub fn user_name(uid: u32) -> String {
+    unsafe {
+        let passwd = libc::getpwuid(uid);
+        if passwd.is_null() {
+            uid.to_string()
+        } else {
+            CStr::from_ptr((*passwd).pw_name)
+                .to_string_lossy()
+                .into_owned()
+        }
+    }
+}
+
+pub fn group_name(gid: u32) -> String {
+    unsafe {
+        let group = libc::getgrgid(gid);
+        if group.is_null() {
+            gid.to_string()
+        } else {
+            CStr::from_ptr((*group).gr_name)
+                .to_string_lossy()
+                .into_owned()
+        }
+    }
+}
+
+// the id of a user name, numeric ids are taken as they are
+pub fn user_id(name: &str) -> Option<u32> {
+    if let Ok(uid) = name.parse() {
+        return Some(uid);
+    }
+    let name = CString::new(name).ok()?;
+    unsafe {
+        let passwd = libc::getpwnam(name.as_ptr());
+        if passwd.is_null() {
+            None
+        } else {
+            Some((*passwd).pw_uid)
+        }
+    }
+}
+
+pub fn group_id(name: &str) -> Option<u32> {
+    if let Ok(gid) = name.parse() {
+        return Some(gid);
+    }
+    let name = CString::new(name).ok()?;
+    unsafe {
+        let group = libc::getgrnam(name.as_ptr());
+        if group.is_null() {
+            None
+        } else {
+            Some((*group).gr_gid)
+        }
+    }
+}
+
 pub fn get_modified(pathb: &PathBuf) -> Option<u64> {
-    let metadata = std::fs::metadata(pathb).ok()?;
+    let metadata = match std::fs::metadata(pathb) {
+        Ok(metadata) => metadata,
+        Err(_) => return vfs::member(pathb)?.modified,
+    };
     let systime = metadata.modified().ok()?;
     match systime.duration_since(SystemTime::UNIX_EPOCH) {
         Ok(n) => Some(n.as_secs()),
         Err(_) => Some(0),
     }
 }
+
+// Points stdout at the controlling terminal and returns the original stdout.
+// Used when mampf's stdout is captured (as in 'mampf --choosefiles -') so
+// that the TUI can still be drawn while the result goes to the caller
+pub fn redirect_stdout_to_tty() -> Result<File, std::io::Error> {
+    let tty = termion::get_tty()?;
+    unsafe {
+        let original = libc::dup(libc::STDOUT_FILENO);
+        if original < 0 || libc::dup2(tty.as_raw_fd(), libc::STDOUT_FILENO) < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(File::from_raw_fd(original))
+    }
+}
+
+// puts text into the system clipboard with wl-copy, xclip or xsel and, if
+// none of them works, asks the terminal to do it (OSC 52, also works over ssh)
+pub fn copy_to_clipboard(text: &str) -> Result<(), std::io::Error> {
+    let mut tools: Vec<&[&str]> = Vec::new();
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        tools.push(&["wl-copy"]);
+    }
+    if std::env::var_os("DISPLAY").is_some() {
+        tools.push(&["xclip", "-selection", "clipboard"]);
+        tools.push(&["xsel", "--clipboard", "--input"]);
+    }
+    for tool in tools {
+        let child = Command::new(tool[0])
+            .args(&tool[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        if let Ok(mut child) = child {
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(text.as_bytes())?;
+            }
+            if child.wait()?.success() {
+                return Ok(());
+            }
+        }
+    }
+    let mut stdout = std::io::stdout();
+    write!(stdout, "\x1b]52;c;{}\x1b\\", base64(text.as_bytes()))?;
+    stdout.flush()
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let buffer = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let triple = u32::from_be_bytes([0, buffer[0], buffer[1], buffer[2]]);
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                encoded.push(ALPHABET[(triple >> (18 - 6 * idx) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
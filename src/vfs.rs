@@ -0,0 +1,156 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+    time::SystemTime,
+};
+
+use crate::archive::{self, Index, Kind, Member};
+
+// A thin layer over the file system that lets archives be browsed like
+// directories. A path inside of an archive is the path of the archive with
+// the member path appended, like ~/backup.tar.gz/etc/fstab. These virtual
+// paths are read-only, their members can only be copied out
+
+// archives are only read again when they changed
+type Stamp = (u64, Option<SystemTime>);
+// the index of every archive, None if it couldn't be read
+type Indexes = HashMap<PathBuf, (Stamp, Option<Arc<Index>>)>;
+// the last member read for the preview, a tar.gz would have to be
+// decompressed up to the member on every refresh otherwise
+type LastRead = Option<(PathBuf, Stamp, Vec<u8>)>;
+
+fn indexes() -> &'static Mutex<Indexes> {
+    static INDEXES: OnceLock<Mutex<Indexes>> = OnceLock::new();
+    INDEXES.get_or_init(Default::default)
+}
+
+fn last_read() -> &'static Mutex<LastRead> {
+    static LAST_READ: OnceLock<Mutex<LastRead>> = OnceLock::new();
+    LAST_READ.get_or_init(Default::default)
+}
+
+fn stamp(archive: &Path) -> Option<Stamp> {
+    let metadata = fs::metadata(archive).ok()?;
+    Some((metadata.len(), metadata.modified().ok()))
+}
+
+fn index_of(archive: &Path) -> Option<Arc<Index>> {
+    let stamp = stamp(archive)?;
+    let mut indexes = indexes().lock().ok()?;
+    match indexes.get(archive) {
+        Some((cached, index)) if cached == &stamp => index.clone(),
+        _ => {
+            // a broken archive is remembered as well, it isn't read again
+            // on every refresh then
+            let index = archive::index(archive).ok().map(Arc::new);
+            indexes.insert(archive.to_path_buf(), (stamp, index.clone()));
+            index
+        }
+    }
+}
+
+pub fn is_archive(pathb: &Path) -> bool {
+    Kind::of(pathb).is_some() && pathb.is_file()
+}
+
+// splits a virtual path into the archive and the member path inside of it
+pub fn split(pathb: &Path) -> Option<(PathBuf, PathBuf)> {
+    // the first existing ancestor has to be the archive
+    let archive = pathb
+        .ancestors()
+        .skip(1)
+        .find(|ancestor| ancestor.exists())?;
+    if !is_archive(archive) {
+        return None;
+    }
+    let member = pathb.strip_prefix(archive).ok()?.to_path_buf();
+    Some((archive.to_path_buf(), member))
+}
+
+pub fn is_virtual(pathb: &Path) -> bool {
+    !pathb.exists() && split(pathb).is_some()
+}
+
+// the member behind a virtual path
+pub fn member(pathb: &Path) -> Option<Member> {
+    let (archive, member) = split(pathb)?;
+    index_of(&archive)?.members.get(&member).cloned()
+}
+
+// whether the path can be listed: directories, archives and directories
+// inside of archives
+pub fn is_dir(pathb: &Path) -> bool {
+    if pathb.exists() {
+        pathb.is_dir() || is_archive(pathb)
+    } else {
+        member(pathb).is_some_and(|member| member.is_dir)
+    }
+}
+
+// like is_dir, but false for archives that weren't read yet. The preview
+// uses it, reading a large archive would block the UI
+pub fn is_dir_cached(pathb: &Path) -> bool {
+    is_dir(pathb) && (!is_archive(pathb) || is_indexed(pathb))
+}
+
+fn is_indexed(archive: &Path) -> bool {
+    let stamp = stamp(archive);
+    indexes().lock().is_ok_and(|indexes| {
+        indexes
+            .get(archive)
+            .is_some_and(|(cached, _)| Some(cached) == stamp.as_ref())
+    })
+}
+
+// the entries of a directory, an archive or a directory inside of one
+pub fn list(dir: &Path) -> Vec<PathBuf> {
+    if dir.is_dir() {
+        return match dir.read_dir() {
+            Ok(entries) => entries.flatten().map(|entry| entry.path()).collect(),
+            Err(_) => Vec::new(),
+        };
+    }
+    let (archive, member) = if is_archive(dir) {
+        (dir.to_path_buf(), PathBuf::new())
+    } else {
+        match split(dir) {
+            Some(split) => split,
+            None => return Vec::new(),
+        }
+    };
+    match index_of(&archive) {
+        Some(index) => index
+            .children
+            .get(&member)
+            .map(|children| children.iter().map(|child| archive.join(child)).collect())
+            .unwrap_or_default(),
+        None => Vec::new(),
+    }
+}
+
+// the start of a file inside of an archive, at most limit bytes
+pub fn read(pathb: &Path, limit: u64) -> Option<Vec<u8>> {
+    let (archive, member) = split(pathb)?;
+    let stamp = stamp(&archive)?;
+    let mut last_read = last_read().lock().ok()?;
+    if let Some((path, cached, content)) = last_read.as_ref() {
+        if path == pathb && cached == &stamp {
+            return Some(content.clone());
+        }
+    }
+    let content = archive::read(&archive, &member, limit).ok()?;
+    *last_read = Some((pathb.to_path_buf(), stamp, content.clone()));
+    Some(content)
+}
+
+// the size in bytes and the number of files of a path inside of an archive
+pub fn tree_size(pathb: &Path) -> (u64, u64) {
+    match split(pathb) {
+        Some((archive, member)) => index_of(&archive)
+            .map(|index| index.tree_size(&member))
+            .unwrap_or((0, 0)),
+        None => (0, 0),
+    }
+}